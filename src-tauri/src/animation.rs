@@ -0,0 +1,257 @@
+//! Keyframed animations, like zoom videos. Centers, rotations and
+//! zoom levels follow smooth (cubic Hermite) splines - the zoom is
+//! interpolated geometrically, so that it keeps a steady pace.
+//! Iterations and palette offsets change linearly between keyframes.
+
+use crate::{
+    color::ColorCreator,
+    data::{AnimationRequest, ExportResult, FractalFragment, Keyframe},
+    fractal::Fractal,
+    renderer::FractalImage,
+    video::{self, Y4mWriter},
+};
+use num::complex::Complex64;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// State of the animation at a single frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frame {
+    pub center: Complex64,
+    pub span: f64,
+    pub rotation: f64,
+    pub max_iterations: u32,
+    pub palette_offset: f64,
+}
+
+/// Index of the keyframe starting the segment, in which time lies
+fn segment(keyframes: &[Keyframe], time: f64) -> usize {
+    let last_segment = keyframes.len().saturating_sub(2);
+    keyframes
+        .iter()
+        .rposition(|key| key.frame as f64 <= time)
+        .unwrap_or(0)
+        .min(last_segment)
+}
+
+/// Position of time within the segment (0.0 - 1.0), and its duration
+fn progress(keyframes: &[Keyframe], id: usize, time: f64) -> (f64, f64) {
+    let start = keyframes[id].frame as f64;
+    let duration = keyframes[id + 1].frame as f64 - start;
+    if duration == 0.0 {
+        return (1.0, duration);
+    }
+    (((time - start) / duration).clamp(0.0, 1.0), duration)
+}
+
+fn linear(keyframes: &[Keyframe], time: f64, value: impl Fn(&Keyframe) -> f64) -> f64 {
+    if keyframes.len() == 1 {
+        return value(&keyframes[0]);
+    }
+    let id = segment(keyframes, time);
+    let (t, _) = progress(keyframes, id, time);
+    let (from, to) = (value(&keyframes[id]), value(&keyframes[id + 1]));
+    from + (to - from) * t
+}
+
+fn spline(keyframes: &[Keyframe], time: f64, value: impl Fn(&Keyframe) -> f64) -> f64 {
+    if keyframes.len() == 1 {
+        return value(&keyframes[0]);
+    }
+    let last = keyframes.len() - 1;
+    let tangent = |id: usize| {
+        let (before, after) = (
+            &keyframes[id.saturating_sub(1)],
+            &keyframes[(id + 1).min(last)],
+        );
+        let duration = after.frame as f64 - before.frame as f64;
+        match duration == 0.0 {
+            true => 0.0,
+            false => (value(after) - value(before)) / duration,
+        }
+    };
+
+    let id = segment(keyframes, time);
+    let (t, duration) = progress(keyframes, id, time);
+    let (t2, t3) = (t * t, t * t * t);
+    (2.0 * t3 - 3.0 * t2 + 1.0) * value(&keyframes[id])
+        + (t3 - 2.0 * t2 + t) * duration * tangent(id)
+        + (-2.0 * t3 + 3.0 * t2) * value(&keyframes[id + 1])
+        + (t3 - t2) * duration * tangent(id + 1)
+}
+
+impl AnimationRequest {
+    pub fn frame_count(&self) -> u32 {
+        let last_frame = self.keyframes.iter().map(|key| key.frame).max();
+        last_frame.map_or(0, |frame| frame + 1)
+    }
+
+    fn sorted_keyframes(&self) -> Vec<Keyframe> {
+        let mut keyframes = self.keyframes.clone();
+        keyframes.sort_by_key(|key| key.frame);
+        keyframes
+    }
+
+    pub fn frames(&self) -> impl Iterator<Item = Frame> {
+        let keyframes = self.sorted_keyframes();
+        (0..self.frame_count()).map(move |frame| {
+            let time = frame as f64;
+            let keys = keyframes.as_slice();
+            Frame {
+                center: Complex64::new(
+                    spline(keys, time, |key| key.center.re),
+                    spline(keys, time, |key| key.center.im),
+                ),
+                span: spline(keys, time, |key| key.span.ln()).exp(),
+                rotation: spline(keys, time, |key| key.rotation),
+                max_iterations: linear(keys, time, |key| key.max_iterations as f64).round() as u32,
+                palette_offset: linear(keys, time, |key| key.palette_offset),
+            }
+        })
+    }
+
+    pub fn frame_image(&self, frame: &Frame) -> FractalImage {
+        let aspect_ratio = self.height_px as f64 / self.width_px as f64;
+        let half = Complex64::new(frame.span, frame.span * aspect_ratio) / 2.0;
+        let fragment = FractalFragment {
+            width_px: self.width_px,
+            height_px: self.height_px,
+            top_left: frame.center + half.conj() * -1.0,
+            bottom_right: frame.center + half.conj(),
+        };
+        let fractal = Fractal::new(frame.max_iterations.max(1), self.variant);
+        let color =
+            ColorCreator::from(self.color.clone()).with_palette_offset(frame.palette_offset);
+        FractalImage::new(fractal, fragment, color).with_rotation(frame.rotation)
+    }
+}
+
+pub fn frame_path(directory: &Path, id: usize) -> PathBuf {
+    directory.join(format!("frame_{id:05}.png"))
+}
+
+/// Saves frames to the requested directory, as a numbered PNG sequence
+fn export_frames(request: &AnimationRequest) -> ExportResult {
+    let directory = Path::new(&request.output);
+    if fs::create_dir_all(directory).is_err() {
+        return ExportResult::ErrorUnknown;
+    }
+
+    for (id, frame) in request.frames().enumerate() {
+        let image = request.frame_image(&frame).render_parallel();
+        let path = frame_path(directory, id);
+        if image.save(path).is_err() {
+            return ExportResult::ErrorUnknown;
+        }
+    }
+    ExportResult::Done
+}
+
+fn stream_frames(request: &AnimationRequest) -> io::Result<()> {
+    let writer = video::open(&request.output)?;
+    let (width, height) = (request.width_px, request.height_px);
+    let mut stream = Y4mWriter::new(writer, width, height, request.frame_rate, request.chroma)?;
+    for frame in request.frames() {
+        let image = request.frame_image(&frame).render_parallel();
+        stream.write_frame(&image)?;
+    }
+    stream.finish()?;
+    Ok(())
+}
+
+/// Streams frames into a Y4M file (or stdout),
+/// or saves them into a directory as PNGs
+pub fn export_animation(request: AnimationRequest) -> ExportResult {
+    if !video::is_y4m_path(&request.output) {
+        return export_frames(&request);
+    }
+    match stream_frames(&request) {
+        Err(_) => ExportResult::ErrorUnknown,
+        Ok(_) => ExportResult::Done,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{data::*, fixtures};
+
+    fn keyframe(frame: u32, re: f64, span: f64) -> Keyframe {
+        Keyframe {
+            frame,
+            center: Complex64::new(re, 0.0),
+            span,
+            rotation: 0.0,
+            max_iterations: 100 + frame,
+            palette_offset: 0.0,
+        }
+    }
+
+    fn request(keyframes: Vec<Keyframe>) -> AnimationRequest {
+        AnimationRequest {
+            variant: FractalVariant::Mandelbrot,
+            color: fixtures::color_config(),
+            keyframes,
+            width_px: 32,
+            height_px: 16,
+            output: "./frames".to_owned(),
+            frame_rate: 30,
+            chroma: Chroma::C420,
+        }
+    }
+
+    #[test]
+    fn passes_through_keyframes() {
+        let keys = vec![
+            keyframe(0, -1.0, 4.0),
+            keyframe(10, 0.5, 0.1),
+            keyframe(30, 0.3, 0.01),
+        ];
+        let frames: Vec<_> = request(keys.clone()).frames().collect();
+        assert_eq!(frames.len(), 31);
+        for key in keys {
+            let frame = frames[key.frame as usize];
+            assert!((frame.center.re - key.center.re).abs() < 1e-12);
+            assert!((frame.span - key.span).abs() < 1e-12);
+            assert_eq!(frame.max_iterations, key.max_iterations);
+        }
+    }
+
+    #[test]
+    fn zooms_geometrically() {
+        let keys = vec![keyframe(0, 0.0, 1.0), keyframe(10, 0.0, 0.001)];
+        let frames: Vec<_> = request(keys).frames().collect();
+        assert!((frames[5].span - 10f64.powf(-1.5)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn sorts_keyframes() {
+        let keys = vec![keyframe(10, 1.0, 1.0), keyframe(0, 0.0, 1.0)];
+        let first = request(keys).frames().next().unwrap();
+        assert_eq!(first.center.re, 0.0);
+    }
+
+    #[test]
+    fn exports_numbered_frames() {
+        let keys = vec![keyframe(0, -0.5, 3.0), keyframe(2, -0.5, 1.0)];
+        let result = export_animation(request(keys));
+        assert!(matches!(result, ExportResult::Done));
+        assert!(Path::new("./frames/frame_00002.png").exists());
+    }
+
+    #[test]
+    fn streams_frames_to_y4m() {
+        let keys = vec![keyframe(0, -0.5, 3.0), keyframe(2, -0.5, 1.0)];
+        let request = AnimationRequest {
+            output: "./clip.y4m".to_owned(),
+            ..request(keys)
+        };
+        let result = export_animation(request);
+        assert!(matches!(result, ExportResult::Done));
+        let frame = 32 * 16 * 3 / 2 + "FRAME\n".len();
+        let size = fs::metadata("./clip.y4m").unwrap().len() as usize;
+        assert!(size > 3 * frame && size < 3 * frame + 64);
+    }
+}
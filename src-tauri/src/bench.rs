@@ -1,9 +1,17 @@
 #![allow(dead_code)]
+mod boundary;
 mod color;
 mod convert;
 mod data;
+mod field;
+#[cfg(test)]
+mod fixtures;
 mod fractal;
+mod pool;
+mod progress;
 mod renderer;
+mod subdivision;
+mod symmetry;
 
 fn main() {
     divan::main();
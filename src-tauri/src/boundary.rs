@@ -0,0 +1,220 @@
+//! Boundary tracing. Evaluation starts from the edges of the image and
+//! spreads only along the contours, where neighbouring pixels fall into
+//! different areas. Areas enclosed by their contours are flood filled
+//! afterwards, row by row from the left. Only the inside of the set is
+//! filled without evaluating it, areas outside of it may enclose others,
+//! which are traced as soon as the fill runs into them.
+
+use crate::{
+    fractal::ComplexItem,
+    progress::Progress,
+    renderer::{Filling, FractalImage},
+};
+
+struct Trace<'a> {
+    image: &'a FractalImage,
+    filling: Filling,
+    width: usize,
+    height: usize,
+    items: Vec<Option<ComplexItem>>,
+    queued: Vec<bool>,
+    queue: Vec<usize>,
+}
+
+impl Trace<'_> {
+    fn load(&mut self, id: usize) -> ComplexItem {
+        let (x, row) = ((id % self.width) as u32, (id / self.width) as u32);
+        *self.items[id].get_or_insert_with(|| self.image.eval_at(x, row))
+    }
+
+    /// Pixels of one area share their key. Areas, which can't be filled,
+    /// are told apart from the others, but not from each other
+    fn key(&self, item: &ComplexItem) -> f64 {
+        match self.filling.fills(item) {
+            true => item.index,
+            false => -1.0,
+        }
+    }
+
+    fn area(&mut self, id: usize) -> f64 {
+        let item = self.load(id);
+        self.key(&item)
+    }
+
+    fn enqueue(&mut self, id: usize) {
+        if !self.queued[id] {
+            self.queued[id] = true;
+            self.queue.push(id);
+        }
+    }
+
+    /// Loads the neighbours of the pixel, and queues those from other areas,
+    /// together with the diagonal ones next to them, so contours stay closed
+    fn scan(&mut self, id: usize) {
+        let (x, row) = (id % self.width, id / self.width);
+        let center = self.area(id);
+        let (left, right) = (x > 0, x + 1 < self.width);
+        let (down, up) = (row > 0, row + 1 < self.height);
+        let w = self.width;
+
+        let l = left && self.area(id - 1) != center;
+        let r = right && self.area(id + 1) != center;
+        let d = down && self.area(id - w) != center;
+        let u = up && self.area(id + w) != center;
+        let w = w as isize;
+        let neighbours = [
+            (l, -1),
+            (r, 1),
+            (d, -w),
+            (u, w),
+            (down && left && (d || l), -w - 1),
+            (down && right && (d || r), -w + 1),
+            (up && left && (u || l), w - 1),
+            (up && right && (u || r), w + 1),
+        ];
+        for (differs, offset) in neighbours {
+            if differs {
+                self.enqueue(id.wrapping_add_signed(offset));
+            }
+        }
+    }
+
+    /// Traces the contours of the queued pixels. False, when cancelled
+    fn run(&mut self, progress: Option<&Progress>) -> bool {
+        while let Some(id) = self.queue.pop() {
+            if progress.is_some_and(Progress::is_cancelled) {
+                return false;
+            }
+            self.scan(id);
+        }
+        true
+    }
+}
+
+/// Items of the image, row by row from the bottom. Cancelled renders
+/// stop while tracing and leave the rest unevaluated
+pub fn render(
+    image: &FractalImage,
+    filling: Filling,
+    progress: Option<&Progress>,
+) -> Vec<ComplexItem> {
+    let fragment = image.fragment();
+    let (width, height) = (fragment.width_px as usize, fragment.height_px as usize);
+    let mut trace = Trace {
+        image,
+        filling,
+        width,
+        height,
+        items: vec![None; width * height],
+        queued: vec![false; width * height],
+        queue: Vec::new(),
+    };
+    for id in 0..width * height {
+        let (x, row) = (id % width, id / width);
+        if x == 0 || row == 0 || x + 1 == width || row + 1 == height {
+            trace.enqueue(id);
+        }
+    }
+    let cancelled = vec![image.unevaluated(); width * height];
+    if !trace.run(progress) {
+        return cancelled;
+    }
+
+    // Every pixel left out lies inside a contour, like the traced one
+    // on its left. The set has no holes, so pixels next to its inside
+    // are copied. Others are evaluated, and start a new trace when
+    // they turn out to be in another area
+    let mut items = Vec::with_capacity(width * height);
+    if width > 0 && height > 0 {
+        let mut traced = trace.load(0);
+        for id in 0..width * height {
+            let item = match trace.items[id] {
+                Some(item) => {
+                    traced = item;
+                    item
+                }
+                None if filling.fills(&traced) && traced.index == traced.max_index => traced,
+                None => {
+                    let item = trace.load(id);
+                    if trace.key(&item) != trace.key(&traced) {
+                        trace.enqueue(id);
+                        if !trace.run(progress) {
+                            return cancelled;
+                        }
+                        traced = item;
+                    }
+                    item
+                }
+            };
+            items.push(item);
+        }
+    }
+    if let Some(progress) = progress {
+        (0..height).for_each(|_| progress.row_done());
+    }
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use num::complex::Complex64;
+
+    fn image(top_left: Complex64, bottom_right: Complex64) -> FractalImage {
+        fixtures::image(90, 70, top_left, bottom_right)
+    }
+
+    #[test]
+    fn matches_full_render() {
+        let image = image(Complex64::new(-2.0, 1.2), Complex64::new(1.0, -1.2));
+        let expected: Vec<_> = (0..70)
+            .flat_map(|row| (0..90).map(move |x| (x, row)))
+            .map(|(x, row)| image.eval_at(x, row).index)
+            .collect();
+        for filling in [Filling::Interior, Filling::Uniform] {
+            let traced = render(&image, filling, None);
+            let indices: Vec<_> = traced.iter().map(|item| item.index).collect();
+            assert_eq!(indices, expected);
+        }
+    }
+
+    #[test]
+    fn traces_set_away_from_edges() {
+        let image = image(Complex64::new(-20.0, 15.0), Complex64::new(20.0, -15.0));
+        let full = image.render_field();
+        let expected: Vec<_> = full.items.iter().map(|item| item.index).collect();
+        for filling in [Filling::Interior, Filling::Uniform] {
+            let traced = render(&image, filling, None);
+            let indices: Vec<_> = traced.iter().map(|item| item.index).collect();
+            assert_eq!(indices, expected);
+        }
+    }
+
+    #[test]
+    fn fills_enclosed_area() {
+        let image = image(Complex64::new(-0.3, 0.2), Complex64::new(0.0, -0.2));
+        let traced = render(&image, Filling::Interior, None);
+        let center = 35 * 90 + 45;
+        assert_eq!(traced[center].value, traced[center - 1].value);
+        assert_ne!(image.eval_at(45, 35).value, traced[center].value);
+    }
+
+    #[test]
+    fn renders_empty_fragments() {
+        let (top_left, bottom_right) = (Complex64::new(-2.0, 1.2), Complex64::new(1.0, -1.2));
+        for (width, height) in [(0, 10), (10, 0), (0, 0)] {
+            let image = fixtures::image(width, height, top_left, bottom_right);
+            assert!(render(&image, Filling::Uniform, None).is_empty());
+        }
+    }
+
+    #[test]
+    fn stops_when_cancelled() {
+        let image = image(Complex64::new(-2.0, 1.2), Complex64::new(1.0, -1.2));
+        let progress = Progress::default();
+        progress.cancel();
+        let items = render(&image, Filling::Uniform, Some(&progress));
+        assert!(items.iter().all(|item| item.index == 0.0));
+    }
+}
@@ -0,0 +1,168 @@
+//! Iteration fields of recently rendered tiles. Tiles requested again
+//! with only their colors changed are recolored from the cached data,
+//! without evaluating the fractal again.
+
+use crate::{
+    data::{FractalConfig, FractalFragment},
+    field::IterationField,
+    renderer::Filling,
+};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+/// About 100 MB worth of ComplexItems (8 tiles of 512 x 512 pixels)
+pub const MAX_CACHED_ITEMS: usize = 8 * 512 * 512;
+
+pub struct FieldCache {
+    /// Least recently used fields come first
+    fields: Mutex<VecDeque<(String, Arc<IterationField>)>>,
+    max_items: usize,
+}
+
+impl FieldCache {
+    pub fn new(max_items: usize) -> Self {
+        Self {
+            fields: Mutex::new(VecDeque::new()),
+            max_items,
+        }
+    }
+
+    /// Fields are identified by everything that affects the iterations.
+    /// Filled fields can only be recolored with colorings, which
+    /// allow the same filling
+    fn key(fractal: &FractalConfig, fragment: &FractalFragment, filling: Filling) -> String {
+        serde_json::to_string(&(fractal, fragment, filling)).unwrap()
+    }
+
+    fn get(&self, key: &str) -> Option<Arc<IterationField>> {
+        let mut fields = self.fields.lock().unwrap();
+        let id = fields.iter().position(|(cached, _)| cached == key)?;
+        let entry = fields.remove(id)?;
+        let field = entry.1.clone();
+        fields.push_back(entry);
+        Some(field)
+    }
+
+    fn insert(&self, key: String, field: Arc<IterationField>) {
+        let mut fields = self.fields.lock().unwrap();
+        fields.retain(|(cached, _)| *cached != key);
+        fields.push_back((key, field));
+        let mut items: usize = fields.iter().map(|(_, field)| field.items.len()).sum();
+        while items > self.max_items && fields.len() > 1 {
+            let (_, evicted) = fields.pop_front().unwrap();
+            items -= evicted.items.len();
+        }
+    }
+
+    /// Cached field, without rendering it
+    pub fn cached(
+        &self,
+        fractal: &FractalConfig,
+        fragment: &FractalFragment,
+        filling: Filling,
+    ) -> Option<Arc<IterationField>> {
+        self.get(&Self::key(fractal, fragment, filling))
+    }
+
+    /// Cached field, or a freshly rendered one. Renders don't block
+    /// the cache, so that tiles can still be computed in parallel.
+    /// Renders, which returned nothing (were cancelled), aren't cached
+    pub fn get_or_render(
+        &self,
+        fractal: &FractalConfig,
+        fragment: &FractalFragment,
+        filling: Filling,
+        render: impl FnOnce() -> Option<IterationField>,
+    ) -> Option<Arc<IterationField>> {
+        let key = Self::key(fractal, fragment, filling);
+        if let Some(field) = self.get(&key) {
+            return Some(field);
+        }
+        let field = Arc::new(render()?);
+        self.insert(key, field.clone());
+        Some(field)
+    }
+}
+
+impl Default for FieldCache {
+    fn default() -> Self {
+        Self::new(MAX_CACHED_ITEMS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::fractal_config;
+    use num::complex::Complex64;
+
+    fn fragment(re: f64) -> FractalFragment {
+        FractalFragment {
+            width_px: 2,
+            height_px: 2,
+            top_left: Complex64::new(re, 1.0),
+            bottom_right: Complex64::new(re + 1.0, 0.0),
+        }
+    }
+
+    #[test]
+    fn renders_each_field_once() {
+        let cache = FieldCache::default();
+        let mut renders = 0;
+        for _ in 0..3 {
+            cache.get_or_render(&fractal_config(), &fragment(0.0), Filling::Off, || {
+                renders += 1;
+                Some(IterationField::new(fragment(0.0), vec![]))
+            });
+        }
+        assert_eq!(renders, 1);
+    }
+
+    #[test]
+    fn skips_cancelled_renders() {
+        let cache = FieldCache::default();
+        assert!(cache
+            .get_or_render(&fractal_config(), &fragment(0.0), Filling::Off, || None)
+            .is_none());
+        let field = || Some(IterationField::new(fragment(0.0), vec![]));
+        assert!(cache
+            .get_or_render(&fractal_config(), &fragment(0.0), Filling::Off, field)
+            .is_some());
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let cache = FieldCache::new(8);
+        let field = |re| {
+            let items =
+                vec![crate::fractal::Fractal::from(fractal_config()).eval(Complex64::default()); 4];
+            Some(IterationField::new(fragment(re), items))
+        };
+        cache.get_or_render(&fractal_config(), &fragment(0.0), Filling::Off, || {
+            field(0.0)
+        });
+        cache.get_or_render(&fractal_config(), &fragment(1.0), Filling::Off, || {
+            field(1.0)
+        });
+        cache.get_or_render(&fractal_config(), &fragment(0.0), Filling::Off, || {
+            field(0.0)
+        });
+        cache.get_or_render(&fractal_config(), &fragment(2.0), Filling::Off, || {
+            field(2.0)
+        });
+
+        let cached = |re| {
+            cache
+                .get(&FieldCache::key(
+                    &fractal_config(),
+                    &fragment(re),
+                    Filling::Off,
+                ))
+                .is_some()
+        };
+        assert!(cached(0.0) && cached(2.0));
+        assert!(!cached(1.0));
+    }
+}
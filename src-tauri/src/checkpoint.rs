@@ -0,0 +1,164 @@
+//! Large exports survive the app being closed. Finished strips are kept
+//! in a checkpoint directory (`<filepath>.parts`), next to a manifest with
+//! the export request. Once every strip is there, the image is assembled
+//! from them and the directory is removed. Resuming an export renders
+//! only the strips, which are still missing.
+
+use crate::{
+    data::{ExportRequest, ExportResult},
+    progress::Progress,
+    renderer::FractalImage,
+    streaming::{self, StreamFormat},
+};
+use std::{
+    ffi::OsString,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// Exports bigger than that (in raw pixels) are checkpointed
+pub const CHECKPOINT_BYTES: u64 = 256 << 20;
+pub const MANIFEST: &str = "manifest.json";
+
+pub fn needs_checkpoints(request: &ExportRequest) -> bool {
+    streaming::estimated_size(&request.fragment) > CHECKPOINT_BYTES
+}
+
+pub fn checkpoint_dir(path: &Path) -> PathBuf {
+    let mut directory = OsString::from(path);
+    directory.push(".parts");
+    directory.into()
+}
+
+fn strip_path(directory: &Path, id: usize) -> PathBuf {
+    directory.join(format!("strip_{id:05}.rgb"))
+}
+
+/// Strips are written under a temporary name first, so that
+/// a strip interrupted halfway is never taken for a finished one
+fn save_strip(path: &Path, pixels: &[u8]) -> io::Result<()> {
+    let partial = path.with_extension("partial");
+    let mut file = fs::File::create(&partial)?;
+    file.write_all(pixels)?;
+    file.sync_all()?;
+    fs::rename(partial, path)
+}
+
+fn run(request: ExportRequest, directory: &Path, progress: &Progress) -> ExportResult {
+    let Some(format) = StreamFormat::from_path(&request.filepath) else {
+        return ExportResult::ErrorBadFileType;
+    };
+    let size = &request.fragment;
+    let rows = streaming::strip_rows(size.width_px);
+    let strip_bytes = |id: usize| {
+        let first_row = id as u64 * rows as u64;
+        let height = (rows as u64).min(size.height_px as u64 - first_row);
+        height * size.width_px as u64 * 3
+    };
+
+    let image = FractalImage::from(request.clone()).with_progress(progress.clone());
+    let strips: Vec<_> = image.into_strips(rows).enumerate().collect();
+    let is_done = |id: usize| {
+        let saved = fs::metadata(strip_path(directory, id));
+        saved.is_ok_and(|saved| saved.len() == strip_bytes(id))
+    };
+    let missing: u64 = (0..strips.len())
+        .filter(|&id| !is_done(id))
+        .map(strip_bytes)
+        .sum();
+    progress.expect_rows(missing / (size.width_px as u64 * 3).max(1));
+    let needed = missing + streaming::estimated_size(size);
+    if !streaming::has_space_for(Path::new(&request.filepath), needed) {
+        return ExportResult::ErrorNoDiskSpace;
+    }
+
+    for (id, strip) in strips {
+        if is_done(id) {
+            continue;
+        }
+        let pixels = strip.render_parallel().into_raw();
+        // Strips of cancelled renders are incomplete
+        if progress.is_cancelled() {
+            return ExportResult::Cancelled;
+        }
+        if save_strip(&strip_path(directory, id), &pixels).is_err() {
+            return ExportResult::ErrorUnknown;
+        }
+    }
+
+    let count = size.height_px.div_ceil(rows) as usize;
+    let saved = (0..count).map(|id| fs::read(strip_path(directory, id)));
+    if streaming::save_strips(Box::new(saved), &request, format).is_err() {
+        return ExportResult::ErrorUnknown;
+    }
+    match fs::remove_dir_all(directory) {
+        Err(_) => ExportResult::ErrorUnknown,
+        Ok(_) => ExportResult::Done,
+    }
+}
+
+/// Starts the export from scratch, dropping checkpoints of earlier attempts
+pub fn export_with_checkpoints(request: ExportRequest, progress: &Progress) -> ExportResult {
+    let directory = checkpoint_dir(Path::new(&request.filepath));
+    if directory.exists() && fs::remove_dir_all(&directory).is_err() {
+        return ExportResult::ErrorUnknown;
+    }
+    let Ok(manifest) = serde_json::to_string_pretty(&request) else {
+        return ExportResult::ErrorUnknown;
+    };
+    if fs::create_dir_all(&directory).is_err()
+        || fs::write(directory.join(MANIFEST), manifest).is_err()
+    {
+        return ExportResult::ErrorUnknown;
+    }
+    run(request, &directory, progress)
+}
+
+pub fn read_manifest(manifest: impl AsRef<Path>) -> Option<ExportRequest> {
+    let json = fs::read_to_string(manifest).ok()?;
+    serde_json::from_str(&json).ok()
+}
+
+/// Continues the export described by the manifest
+pub fn resume_export(
+    request: ExportRequest,
+    manifest: impl AsRef<Path>,
+    progress: &Progress,
+) -> ExportResult {
+    match manifest.as_ref().parent() {
+        None => ExportResult::ErrorUnknown,
+        Some(directory) => run(request, directory, progress),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::export_request;
+
+    #[test]
+    fn names_checkpoint_dir_after_export() {
+        let directory = checkpoint_dir(Path::new("exports/big.png"));
+        assert_eq!(directory, Path::new("exports/big.png.parts"));
+    }
+
+    #[test]
+    fn resumes_from_saved_strips() {
+        let request = export_request("./resumed.png");
+        let directory = checkpoint_dir(Path::new("./resumed.png"));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let manifest = directory.join(MANIFEST);
+        fs::write(&manifest, serde_json::to_string(&request).unwrap()).unwrap();
+        let request = read_manifest(&manifest).unwrap();
+        // A strip of garbage, of the right size, has to be used as is
+        fs::write(strip_path(&directory, 0), vec![7; 40 * 30 * 3]).unwrap();
+
+        let result = resume_export(request, &manifest, &Progress::default());
+        assert!(matches!(result, ExportResult::Done));
+        assert!(!directory.exists());
+        let saved = image::open("./resumed.png").unwrap().into_rgb8();
+        assert!(saved.as_raw().iter().all(|&value| value == 7));
+    }
+}
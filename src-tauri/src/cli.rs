@@ -0,0 +1,521 @@
+//! Headless renderer. Renders scene files, or fractals described
+//! with command line flags, without starting the app (or a webview)
+#![allow(dead_code)]
+mod animation;
+mod boundary;
+mod cache;
+mod checkpoint;
+mod color;
+mod convert;
+mod data;
+mod export;
+mod field;
+#[cfg(test)]
+mod fixtures;
+mod fractal;
+mod hdr;
+mod jobs;
+mod loops;
+mod metadata;
+mod pool;
+mod progress;
+mod progressive;
+mod renderer;
+mod scene;
+mod streaming;
+mod subdivision;
+mod symmetry;
+mod tiles;
+mod video;
+
+use clap::{Parser, ValueEnum};
+use data::*;
+use fractal::CYCLE_TOLERANCE;
+use num::complex::Complex64;
+use progress::Progress;
+use scene::{ExportSettings, Scene, Selection, SCENE_VERSION};
+use std::{fs, path::PathBuf, process::ExitCode};
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Variant {
+    Mandelbrot,
+    Julia,
+    BurningShip,
+    Newton,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Method {
+    Raw,
+    Linear,
+    Stripes,
+    Exponential,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Strategy {
+    Off,
+    Subdivision,
+    BoundaryTracing,
+}
+
+impl From<Strategy> for renderer::Strategy {
+    fn from(value: Strategy) -> Self {
+        match value {
+            Strategy::Off => renderer::Strategy::Off,
+            Strategy::Subdivision => renderer::Strategy::Subdivision,
+            Strategy::BoundaryTracing => renderer::Strategy::BoundaryTracing,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Subsampling {
+    #[value(name = "420")]
+    C420,
+    #[value(name = "444")]
+    C444,
+}
+
+impl From<Subsampling> for Chroma {
+    fn from(value: Subsampling) -> Self {
+        match value {
+            Subsampling::C420 => Chroma::C420,
+            Subsampling::C444 => Chroma::C444,
+        }
+    }
+}
+
+/// Renders a fractal to a file. Flags override the settings of --scene
+#[derive(Parser)]
+#[command(name = "fractals-cli")]
+struct Args {
+    /// Scene file (.fractal.json) to render
+    #[arg(long)]
+    scene: Option<PathBuf>,
+    /// Keyframes of an animation (JSON), rendered instead of a single image
+    #[arg(long, conflicts_with = "scene")]
+    animation: Option<PathBuf>,
+    /// Output file, its extension picks the format (png, jpeg, exr, npy, raw, y4m...).
+    /// Directory for the frames, when rendering an animation.
+    /// "-" streams Y4M video to stdout
+    #[arg(short, long)]
+    output: String,
+    /// Frames per second of Y4M animations
+    #[arg(long)]
+    frame_rate: Option<u32>,
+    /// Chroma subsampling of Y4M outputs
+    #[arg(long, value_enum)]
+    chroma: Option<Subsampling>,
+    /// Width of the output, in pixels
+    #[arg(long)]
+    width: Option<u32>,
+    /// Height of the output, in pixels (follows the aspect ratio of the scene by default)
+    #[arg(long)]
+    height: Option<u32>,
+    /// Threads to render on (one per CPU core by default)
+    #[arg(long)]
+    threads: Option<usize>,
+    /// JPEG quality
+    #[arg(long, default_value_t = 90, value_parser = clap::value_parser!(u8).range(1..=100))]
+    quality: u8,
+    #[arg(long, value_enum)]
+    variant: Option<Variant>,
+    /// Constant of the Julia set, as "re,im"
+    #[arg(long, value_parser = parse_complex, allow_hyphen_values = true)]
+    constant: Option<Complex64>,
+    #[arg(long)]
+    iterations: Option<u32>,
+    /// How close an orbit has to come back to itself, to count as a cycle
+    #[arg(long)]
+    cycle_tolerance: Option<f64>,
+    /// Center of the output in the complex plane, as "re,im"
+    #[arg(long, value_parser = parse_complex, allow_hyphen_values = true)]
+    center: Option<Complex64>,
+    /// Width of the output in the complex plane
+    #[arg(long)]
+    span: Option<f64>,
+    /// First color of the gradient, as "#rrggbb"
+    #[arg(long)]
+    color_start: Option<String>,
+    /// Last color of the gradient, as "#rrggbb"
+    #[arg(long)]
+    color_end: Option<String>,
+    #[arg(long)]
+    brightness: Option<f64>,
+    #[arg(long, value_enum)]
+    method: Option<Method>,
+    /// Power of the exponential coloring method
+    #[arg(long, default_value_t = 0.8)]
+    power: f64,
+    #[arg(long)]
+    no_anti_alias: bool,
+    /// Adds smooth_index and distance channels to OpenEXR outputs
+    #[arg(long)]
+    extra_channels: bool,
+    /// How areas of one color are found, to skip evaluating them.
+    /// Off by default, skipping can miss thin filaments and islands
+    #[arg(long, value_enum)]
+    strategy: Option<Strategy>,
+}
+
+fn parse_complex(arg: &str) -> Result<Complex64, String> {
+    let (re, im) = arg
+        .split_once(',')
+        .ok_or("expected two numbers, like -0.7,0.25")?;
+    let parse = |part: &str| part.trim().parse::<f64>().map_err(|e| e.to_string());
+    Ok(Complex64::new(parse(re)?, parse(im)?))
+}
+
+fn viewport(center: Complex64, span: f64, width_px: u32, height_px: u32) -> FractalFragment {
+    let half = Complex64::new(span, span * height_px as f64 / width_px as f64) / 2.0;
+    FractalFragment {
+        width_px,
+        height_px,
+        top_left: Complex64::new(center.re - half.re, center.im + half.im),
+        bottom_right: Complex64::new(center.re + half.re, center.im - half.im),
+    }
+}
+
+/// Fragment of the complex plane (and its size in pixels)
+/// described by the export settings of the scene
+fn export_fragment(scene: &Scene) -> FractalFragment {
+    let view = &scene.viewport;
+    let selection = scene.export.selection.unwrap_or(Selection {
+        left: 0.0,
+        top: 0.0,
+        right: 1.0,
+        bottom: 1.0,
+    });
+    let width = view.bottom_right.re - view.top_left.re;
+    let height = view.top_left.im - view.bottom_right.im;
+    let at = |x: f64, y: f64| {
+        Complex64::new(view.top_left.re + x * width, view.top_left.im - y * height)
+    };
+
+    let top_left = at(selection.left, selection.top);
+    let bottom_right = at(selection.right, selection.bottom);
+    let aspect_ratio = (bottom_right.re - top_left.re) / (top_left.im - bottom_right.im);
+    FractalFragment {
+        width_px: scene.export.width_px,
+        height_px: (scene.export.width_px as f64 / aspect_ratio) as u32,
+        top_left,
+        bottom_right,
+    }
+}
+
+fn default_scene() -> Scene {
+    Scene {
+        version: SCENE_VERSION,
+        fractal: FractalConfig {
+            variant: FractalVariant::Mandelbrot,
+            max_iterations: 256,
+            cycle_tolerance: CYCLE_TOLERANCE,
+        },
+        color: ColorConfig {
+            color: ColorHex {
+                hex_start: "#ff0000".to_owned(),
+                hex_end: "#ffff00".to_owned(),
+            },
+            brightness: 1.0,
+            anti_alias: true,
+            method: ColorMethod::Linear,
+        },
+        viewport: viewport(Complex64::new(-0.7, 0.0), 3.5, 1920, 1080),
+        export: ExportSettings {
+            width_px: 1920,
+            selection: None,
+        },
+    }
+}
+
+impl Args {
+    fn apply_fractal(&self, fractal: &mut FractalConfig) {
+        let constant = match fractal.variant {
+            FractalVariant::JuliaSet { constant } => constant,
+            _ => Complex64::new(0.304, -0.436),
+        };
+        let constant = self.constant.unwrap_or(constant);
+        fractal.variant = match self.variant {
+            None => match fractal.variant {
+                FractalVariant::JuliaSet { .. } => FractalVariant::JuliaSet { constant },
+                variant => variant,
+            },
+            Some(Variant::Julia) => FractalVariant::JuliaSet { constant },
+            Some(Variant::Mandelbrot) => FractalVariant::Mandelbrot,
+            Some(Variant::BurningShip) => FractalVariant::BurningShip,
+            Some(Variant::Newton) => FractalVariant::Newton,
+        };
+        if let Some(iterations) = self.iterations {
+            fractal.max_iterations = iterations;
+        }
+        if let Some(tolerance) = self.cycle_tolerance {
+            fractal.cycle_tolerance = tolerance;
+        }
+    }
+
+    fn apply_color(&self, color: &mut ColorConfig) {
+        if let Some(hex) = &self.color_start {
+            color.color.hex_start = hex.clone();
+        }
+        if let Some(hex) = &self.color_end {
+            color.color.hex_end = hex.clone();
+        }
+        if let Some(brightness) = self.brightness {
+            color.brightness = brightness;
+        }
+        if self.no_anti_alias {
+            color.anti_alias = false;
+        }
+        color.method = match self.method {
+            None => color.method,
+            Some(Method::Raw) => ColorMethod::Raw,
+            Some(Method::Linear) => ColorMethod::Linear,
+            Some(Method::Stripes) => ColorMethod::Stripes,
+            Some(Method::Exponential) => ColorMethod::Exponential { power: self.power },
+        };
+    }
+
+    fn apply_viewport(&self, scene: &mut Scene) {
+        if self.center.is_none() && self.span.is_none() {
+            return;
+        }
+        let view = &scene.viewport;
+        let center = (view.top_left + view.bottom_right) / 2.0;
+        let span = view.bottom_right.re - view.top_left.re;
+        scene.viewport = viewport(
+            self.center.unwrap_or(center),
+            self.span.unwrap_or(span),
+            view.width_px,
+            view.height_px,
+        );
+        scene.export.selection = None;
+    }
+
+    fn export_fragment(&self, scene: &Scene) -> FractalFragment {
+        let fragment = export_fragment(scene);
+        let Some(height_px) = self.height else {
+            return fragment;
+        };
+        // Keep the pixels square, and the center in place
+        let center = (fragment.top_left + fragment.bottom_right) / 2.0;
+        let span = fragment.bottom_right.re - fragment.top_left.re;
+        viewport(center, span, fragment.width_px, height_px)
+    }
+
+    fn into_request(self) -> Result<ExportRequest, String> {
+        let mut scene = match &self.scene {
+            None => default_scene(),
+            Some(path) => Scene::load(path)
+                .map_err(|err| format!("Could not load {}: {err:?}", path.display()))?,
+        };
+        self.apply_fractal(&mut scene.fractal);
+        self.apply_color(&mut scene.color);
+        self.apply_viewport(&mut scene);
+        if let Some(width_px) = self.width {
+            scene.export.width_px = width_px;
+        }
+
+        Ok(ExportRequest {
+            fragment: self.export_fragment(&scene),
+            fractal: scene.fractal,
+            color: scene.color,
+            filepath: self.output,
+            extra_channels: self.extra_channels,
+            quality: self.quality,
+            chroma: self.chroma.map(Chroma::from).unwrap_or_default(),
+            animation: None,
+            strategy: self
+                .strategy
+                .map(renderer::Strategy::from)
+                .unwrap_or_default(),
+        })
+    }
+}
+
+impl Args {
+    fn into_animation(self, path: &PathBuf) -> Result<AnimationRequest, String> {
+        let json = fs::read_to_string(path)
+            .map_err(|err| format!("Could not read {}: {err}", path.display()))?;
+        let mut request: AnimationRequest = serde_json::from_str(&json)
+            .map_err(|err| format!("Bad keyframes in {}: {err}", path.display()))?;
+        let mut fractal = FractalConfig {
+            variant: request.variant,
+            max_iterations: 0,
+            cycle_tolerance: CYCLE_TOLERANCE,
+        };
+        self.apply_fractal(&mut fractal);
+        self.apply_color(&mut request.color);
+        request.variant = fractal.variant;
+        request.width_px = self.width.unwrap_or(request.width_px);
+        request.height_px = self.height.unwrap_or(request.height_px);
+        request.frame_rate = self.frame_rate.unwrap_or(request.frame_rate);
+        if let Some(chroma) = self.chroma {
+            request.chroma = chroma.into();
+        }
+        request.output = self.output;
+        Ok(request)
+    }
+}
+
+fn render_animation(args: Args, path: PathBuf) -> ExitCode {
+    let request = match args.into_animation(&path) {
+        Ok(request) => request,
+        Err(message) => {
+            eprintln!("{message}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let (frames, output) = (request.frame_count(), request.output.clone());
+    match animation::export_animation(request) {
+        ExportResult::Done => {
+            // stdout may be taken by the video stream
+            eprintln!("Saved {frames} frames to {output}");
+            ExitCode::SUCCESS
+        }
+        _ => {
+            eprintln!("Could not render or save frames to {output}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    if let Some(threads) = args.threads {
+        pool::set_threads(threads);
+    }
+    if let Some(path) = args.animation.clone() {
+        return render_animation(args, path);
+    }
+    let request = match args.into_request() {
+        Ok(request) => request,
+        Err(message) => {
+            eprintln!("{message}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let path = request.filepath.clone();
+    let progress = Progress::new(|fraction| eprint!("\rRendering {:3.0}%", fraction * 100.0));
+    let result = export::export_to_file(request, &progress);
+    eprintln!();
+    match result {
+        ExportResult::Done => {
+            eprintln!("Saved {path}");
+            ExitCode::SUCCESS
+        }
+        ExportResult::ErrorBadFileType => {
+            eprintln!("Unsupported file type: {path}");
+            ExitCode::FAILURE
+        }
+        ExportResult::ErrorNoDiskSpace => {
+            eprintln!("Not enough free disk space for {path}");
+            ExitCode::FAILURE
+        }
+        ExportResult::ErrorUnknown => {
+            eprintln!("Could not render or save {path}");
+            ExitCode::FAILURE
+        }
+        ExportResult::Cancelled => {
+            eprintln!("Cancelled, {path} was not saved");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_complex_numbers() {
+        let parsed = parse_complex("-0.7, 0.25").unwrap();
+        assert_eq!(parsed, Complex64::new(-0.7, 0.25));
+        assert!(parse_complex("-0.7").is_err());
+    }
+
+    #[test]
+    fn calculates_export_fragment() {
+        let mut scene = default_scene();
+        scene.viewport = viewport(Complex64::new(0.0, 0.0), 4.0, 800, 400);
+        scene.export = ExportSettings {
+            width_px: 1000,
+            selection: Some(Selection {
+                left: 0.5,
+                top: 0.0,
+                right: 1.0,
+                bottom: 0.5,
+            }),
+        };
+
+        let fragment = export_fragment(&scene);
+        assert_eq!(fragment.top_left, Complex64::new(0.0, 1.0));
+        assert_eq!(fragment.bottom_right, Complex64::new(2.0, 0.0));
+        assert_eq!(fragment.height_px, 500);
+    }
+
+    #[test]
+    fn keeps_pixels_square_when_height_is_given() {
+        let args = Args::parse_from(["fractals-cli", "-o", "out.png", "--height", "100"]);
+        let request = args.into_request().unwrap();
+        let fragment = request.fragment;
+        let pixel_width = (fragment.bottom_right.re - fragment.top_left.re) / 1920.0;
+        let pixel_height = (fragment.top_left.im - fragment.bottom_right.im) / 100.0;
+        assert!((pixel_width - pixel_height).abs() < 1e-12);
+    }
+
+    #[test]
+    fn picks_render_strategy() {
+        let request = Args::parse_from(["fractals-cli", "-o", "out.png"])
+            .into_request()
+            .unwrap();
+        assert_eq!(request.strategy, renderer::Strategy::Off);
+        let args = [
+            "fractals-cli",
+            "-o",
+            "out.png",
+            "--strategy",
+            "boundary-tracing",
+        ];
+        let request = Args::parse_from(args).into_request().unwrap();
+        assert_eq!(request.strategy, renderer::Strategy::BoundaryTracing);
+    }
+
+    #[test]
+    fn reads_animation_keyframes() {
+        let keyframes = r##"{
+            "variant": { "type": "Mandelbrot" },
+            "color": {
+                "color": { "hex_start": "#ff0000", "hex_end": "#ffff00" },
+                "brightness": 1.0,
+                "anti_alias": true,
+                "method": { "type": "Linear" }
+            },
+            "keyframes": [
+                { "frame": 0, "center": { "re": -0.5, "im": 0.0 }, "span": 3.0, "max_iterations": 64 },
+                { "frame": 9, "center": { "re": -0.7, "im": 0.2 }, "span": 0.5, "max_iterations": 128 }
+            ],
+            "width_px": 64,
+            "height_px": 36
+        }"##;
+        fs::write("./keyframes.json", keyframes).unwrap();
+        let args = Args::parse_from([
+            "fractals-cli",
+            "--animation",
+            "x",
+            "-o",
+            "./clip",
+            "--width",
+            "32",
+        ]);
+        let request = args
+            .into_animation(&PathBuf::from("./keyframes.json"))
+            .unwrap();
+        assert_eq!(request.frame_count(), 10);
+        assert_eq!(request.width_px, 32);
+        assert_eq!(request.output, "./clip");
+        assert_eq!(request.frame_rate, 30);
+    }
+}
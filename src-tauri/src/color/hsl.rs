@@ -1,4 +1,4 @@
-use super::clip;
+use super::{clip, srgb_to_linear};
 use crate::data::Rgb;
 
 /// Adapted from https://stackoverflow.com/questions/2353211/hsl-to-rgb-color-conversion
@@ -21,10 +21,10 @@ fn hue_to_rgb(p: f64, q: f64, mut t: f64) -> f64 {
     }
 }
 
-pub fn hsl_to_rgb(h: f64, s: f64, l: f64) -> Rgb {
+/// Channels are not clipped, so they may end up outside of <0, 1>
+pub fn hsl_to_rgb_float(h: f64, s: f64, l: f64) -> [f64; 3] {
     if s == 0.0 {
-        let luma = clip(l * 256.0);
-        return Rgb::from([luma, luma, luma]);
+        return [l, l, l];
     };
 
     let q = if l < 0.5 {
@@ -36,6 +36,11 @@ pub fn hsl_to_rgb(h: f64, s: f64, l: f64) -> Rgb {
     let r = hue_to_rgb(p, q, h + 1.0 / 3.0);
     let g = hue_to_rgb(p, q, h);
     let b = hue_to_rgb(p, q, h - 1.0 / 3.0);
+    [r, g, b]
+}
+
+pub fn hsl_to_rgb(h: f64, s: f64, l: f64) -> Rgb {
+    let [r, g, b] = hsl_to_rgb_float(h, s, l);
     Rgb::from([clip(r * 256.0), clip(g * 256.0), clip(b * 256.0)])
 }
 
@@ -125,6 +130,16 @@ impl ColorGradient {
             self.transition(2, step),
         )
     }
+
+    /// Same as color_for, but in linear light and without clipping
+    pub fn linear_color_for(&self, step: f64) -> [f32; 3] {
+        let rgb = hsl_to_rgb_float(
+            self.transition_hue(0, step),
+            self.transition(1, step),
+            self.transition(2, step),
+        );
+        rgb.map(|channel| srgb_to_linear(channel) as f32)
+    }
 }
 
 #[cfg(test)]
@@ -5,6 +5,7 @@ use self::utils::*;
 use crate::{
     data::{ColorHex, ColorMethod, Rgb},
     fractal::ComplexItem,
+    renderer::Filling,
 };
 
 impl ComplexItem {
@@ -17,6 +18,15 @@ impl ComplexItem {
         // fractal equation so technically... to be corrected
     }
 
+    /// 'Real iteration number', or max_index for points inside the fractal
+    pub fn smooth_index(&self) -> f64 {
+        if self.index == self.max_index {
+            return self.max_index;
+        }
+
+        self.index - self.anti_alias()
+    }
+
     fn normalized_no_aa(&self) -> f64 {
         if self.index == self.max_index {
             return 0.0;
@@ -43,6 +53,7 @@ pub struct ColorCreator {
     brightness: f64,
     anti_alias: bool,
     method: ColorMethod,
+    palette_offset: Option<f64>,
 }
 
 impl ColorCreator {
@@ -51,6 +62,7 @@ impl ColorCreator {
             brightness,
             anti_alias,
             method,
+            palette_offset: None,
             gradient: ColorGradient::new(
                 &hex_to_color(color.hex_start),
                 &hex_to_color(color.hex_end),
@@ -58,6 +70,27 @@ impl ColorCreator {
         }
     }
 
+    /// Shifts the phase of the palette. With an offset set, the gradient
+    /// is walked back and forth, instead of being clipped at its ends
+    pub fn with_palette_offset(self, offset: f64) -> Self {
+        Self {
+            palette_offset: Some(offset),
+            ..self
+        }
+    }
+
+    /// The most filling, which colors filled areas like evaluated ones.
+    /// Anti-aliasing and stripes need the values of every pixel outside
+    /// the set, raw anti-aliased colors need them inside of it too
+    pub fn filling(&self) -> Filling {
+        match (self.method, self.anti_alias) {
+            (ColorMethod::Stripes, _) => Filling::Interior,
+            (ColorMethod::Raw, true) => Filling::Off,
+            (_, true) => Filling::Interior,
+            (_, false) => Filling::Uniform,
+        }
+    }
+
     fn raw(&self, item: &ComplexItem) -> f64 {
         let mut id = item.index;
         if self.anti_alias {
@@ -93,7 +126,7 @@ impl ColorCreator {
         (-aa).powf(3.0)
     }
 
-    pub fn get_pixel(&self, item: &ComplexItem) -> Rgb {
+    fn luma(&self, item: &ComplexItem) -> f64 {
         use ColorMethod::*;
         let base = match self.method {
             Raw => self.raw(item),
@@ -103,6 +136,18 @@ impl ColorCreator {
         };
 
         let luma = base * self.brightness;
-        self.gradient.color_for(luma)
+        match self.palette_offset {
+            None => luma,
+            Some(offset) => ping_pong(luma + offset),
+        }
+    }
+
+    pub fn get_pixel(&self, item: &ComplexItem) -> Rgb {
+        self.gradient.color_for(self.luma(item))
+    }
+
+    /// Linear-light color, not quantized to u8 (for HDR exports)
+    pub fn get_linear_pixel(&self, item: &ComplexItem) -> [f32; 3] {
+        self.gradient.linear_color_for(self.luma(item))
     }
 }
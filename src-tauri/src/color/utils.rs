@@ -4,6 +4,25 @@ pub fn clip(input: f64) -> u8 {
     input.round().max(0.0).min(255.0) as u8
 }
 
+/// Undoes sRGB gamma. Values above 1 are extrapolated, instead of clipped
+pub fn srgb_to_linear(channel: f64) -> f64 {
+    if channel <= 0.04045 {
+        channel / 12.92
+    } else {
+        ((channel + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Folds any value into <0, 1>, going back and forth (triangle wave)
+pub fn ping_pong(arg: f64) -> f64 {
+    let phase = arg.rem_euclid(2.0);
+    if phase > 1.0 {
+        2.0 - phase
+    } else {
+        phase
+    }
+}
+
 pub fn _normalize(pixel: u8) -> f64 {
     pixel as f64 / 256.0
 }
@@ -77,6 +96,22 @@ mod tests {
         assert_eq!(b, 0);
     }
 
+    #[test]
+    fn linearizes_srgb() {
+        assert_eq!(super::srgb_to_linear(0.0), 0.0);
+        assert!((super::srgb_to_linear(1.0) - 1.0).abs() < 1e-9);
+        assert!((super::srgb_to_linear(0.5) - 0.214).abs() < 1e-3);
+        assert!(super::srgb_to_linear(2.0) > 1.0);
+    }
+
+    #[test]
+    fn ping_pongs_between_zero_and_one() {
+        assert_eq!(super::ping_pong(0.25), 0.25);
+        assert_eq!(super::ping_pong(1.25), 0.75);
+        assert_eq!(super::ping_pong(2.25), 0.25);
+        assert_eq!(super::ping_pong(-0.25), 0.25);
+    }
+
     #[test]
     #[should_panic]
     fn panics_on_invalid_hex() {
@@ -1,4 +1,4 @@
-use crate::data::{ColorConfig, ExportRequest, FractalConfig};
+use crate::data::{ColorConfig, ExportRequest, FractalConfig, RenderParams};
 use crate::fractal::*;
 use crate::renderer::FractalImage;
 use crate::{color::ColorCreator, data::TileRequest};
@@ -17,6 +17,7 @@ impl From<ColorConfig> for ColorCreator {
 impl From<FractalConfig> for Fractal {
     fn from(value: FractalConfig) -> Self {
         Fractal::new(value.max_iterations, value.variant)
+            .with_cycle_tolerance(value.cycle_tolerance)
     }
 }
 
@@ -26,8 +27,22 @@ impl From<TileRequest> for FractalImage {
     }
 }
 
+/// Exports with a strategy fill as much as their coloring allows
 impl From<ExportRequest> for FractalImage {
     fn from(value: ExportRequest) -> Self {
-        FractalImage::new(value.fractal.into(), value.fragment, value.color.into())
+        let color: ColorCreator = value.color.into();
+        FractalImage::new(value.fractal.into(), value.fragment, color)
+            .with_filling(color.filling())
+            .with_strategy(value.strategy)
+    }
+}
+
+impl From<&ExportRequest> for RenderParams {
+    fn from(value: &ExportRequest) -> Self {
+        RenderParams {
+            fractal: value.fractal.clone(),
+            fragment: value.fragment.clone(),
+            color: value.color.clone(),
+        }
     }
 }
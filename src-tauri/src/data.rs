@@ -1,15 +1,16 @@
+use crate::{fractal::CYCLE_TOLERANCE, renderer::Strategy};
 use num::complex::Complex64;
 use serde::{Deserialize, Serialize};
 pub type Rgb = image::Rgb<u8>;
 
-#[derive(Deserialize, Clone, Copy)]
+#[derive(Serialize, Deserialize, Clone, Copy)]
 #[serde(remote = "Complex64")]
 struct ComplexDef {
     im: f64,
     re: f64,
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct FractalFragment {
     pub height_px: u32,
     pub width_px: u32,
@@ -19,7 +20,7 @@ pub struct FractalFragment {
     pub bottom_right: Complex64,
 }
 
-#[derive(Deserialize, Clone, Copy)]
+#[derive(Serialize, Deserialize, Clone, Copy)]
 #[serde(tag = "type")]
 pub enum ColorMethod {
     Raw,
@@ -28,13 +29,13 @@ pub enum ColorMethod {
     Exponential { power: f64 },
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct ColorHex {
     pub hex_start: String,
     pub hex_end: String,
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct ColorConfig {
     pub color: ColorHex,
     pub brightness: f64,
@@ -42,7 +43,7 @@ pub struct ColorConfig {
     pub method: ColorMethod,
 }
 
-#[derive(Deserialize, Clone, Copy)]
+#[derive(Serialize, Deserialize, Clone, Copy)]
 #[serde(tag = "type")]
 pub enum FractalVariant {
     Newton,
@@ -54,30 +55,192 @@ pub enum FractalVariant {
     },
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct FractalConfig {
     pub variant: FractalVariant,
     pub max_iterations: u32,
+    /// See Fractal::with_cycle_tolerance
+    #[serde(default = "default_cycle_tolerance")]
+    pub cycle_tolerance: f64,
 }
 
+/// Everything needed to render the same picture again
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RenderParams {
+    pub fractal: FractalConfig,
+    pub fragment: FractalFragment,
+    pub color: ColorConfig,
+}
+
+pub type TileId = u64;
+
 #[derive(Deserialize, Clone)]
 pub struct TileRequest {
     pub fractal: FractalConfig,
     pub fragment: FractalFragment,
     pub color: ColorConfig,
+    pub id: TileId,
+    /// Started by the frontend when it loads, see `start_tile_session`
+    pub session: u32,
+    /// Bumped by the frontend, whenever the tiles on screen become outdated
+    pub generation: u32,
+    /// Renders coarse passes first, see progressive.rs
+    #[serde(default)]
+    pub progressive: bool,
+    /// How uniform areas of the tile are found
+    #[serde(default)]
+    pub strategy: Strategy,
 }
 
 #[derive(Deserialize, Clone)]
+pub struct BatchTile {
+    pub id: TileId,
+    pub fragment: FractalFragment,
+    /// Tiles with lower priorities are rendered first
+    pub priority: f64,
+}
+
+/// Tiles sharing the fractal and the colors
+#[derive(Deserialize, Clone)]
+pub struct TileBatch {
+    pub fractal: FractalConfig,
+    pub color: ColorConfig,
+    pub session: u32,
+    pub generation: u32,
+    #[serde(default)]
+    pub progressive: bool,
+    #[serde(default)]
+    pub strategy: Strategy,
+    pub tiles: Vec<BatchTile>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct ExportRequest {
     pub fractal: FractalConfig,
     pub fragment: FractalFragment,
     pub color: ColorConfig,
     pub filepath: String,
+    /// Adds smooth_index and distance channels to OpenEXR exports
+    #[serde(default)]
+    pub extra_channels: bool,
+    /// JPEG quality, 1 - 100
+    #[serde(default = "default_quality")]
+    pub quality: u8,
+    /// Chroma subsampling of Y4M exports
+    #[serde(default)]
+    pub chroma: Chroma,
+    /// Turns the export into a looping GIF or APNG
+    #[serde(default)]
+    pub animation: Option<LoopAnimation>,
+    /// How uniform areas of the export are found, by default
+    /// every pixel is evaluated
+    #[serde(default)]
+    pub strategy: Strategy,
+}
+
+/// Complex number, for use in collections
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct ComplexPoint(#[serde(with = "ComplexDef")] pub Complex64);
+
+/// Path of a Julia set constant, through the Mandelbrot plane
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "type")]
+pub enum ConstantPath {
+    /// Straight segments between the points, walked at a steady speed
+    Polyline { points: Vec<ComplexPoint> },
+    Circle {
+        #[serde(with = "ComplexDef")]
+        center: Complex64,
+        radius: f64,
+    },
+    /// Boundary of the main cardioid of the Mandelbrot set
+    Cardioid,
+}
+
+/// What changes between the frames of a loop
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "type")]
+pub enum LoopMotion {
+    /// Julia set, with its constant moving along the path
+    JuliaMorph { path: ConstantPath },
+    /// The palette shifts by a full cycle
+    PaletteCycle,
+    /// Zooms into the center of the fragment, by the factor
+    Zoom { factor: f64 },
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LoopAnimation {
+    pub motion: LoopMotion,
+    pub frames: u32,
+    /// Time between frames, in milliseconds
+    pub delay_ms: u32,
+    /// Floyd-Steinberg dithering of GIF palettes
+    #[serde(default)]
+    pub dither: bool,
+}
+
+fn default_quality() -> u8 {
+    75
+}
+
+fn default_cycle_tolerance() -> f64 {
+    CYCLE_TOLERANCE
+}
+
+/// Chroma subsampling of YUV4MPEG2 (Y4M) streams
+#[derive(Serialize, Deserialize, Clone, Copy, Default, Debug, PartialEq)]
+pub enum Chroma {
+    /// Color at half the resolution, in both directions
+    #[default]
+    C420,
+    /// Color at full resolution
+    C444,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+pub struct Keyframe {
+    /// Frame at which the animation passes through this keyframe
+    pub frame: u32,
+    #[serde(with = "ComplexDef")]
+    pub center: Complex64,
+    /// Width of the frame in the complex plane
+    pub span: f64,
+    /// Counterclockwise, in radians
+    #[serde(default)]
+    pub rotation: f64,
+    pub max_iterations: u32,
+    #[serde(default)]
+    pub palette_offset: f64,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct AnimationRequest {
+    pub variant: FractalVariant,
+    pub color: ColorConfig,
+    pub keyframes: Vec<Keyframe>,
+    pub width_px: u32,
+    pub height_px: u32,
+    /// Either a .y4m file (or "-" for stdout) to stream the frames to,
+    /// or a directory to save them in as frame_00000.png, frame_00001.png...
+    #[serde(default)]
+    pub output: String,
+    /// Frames per second of Y4M streams
+    #[serde(default = "default_frame_rate")]
+    pub frame_rate: u32,
+    #[serde(default)]
+    pub chroma: Chroma,
+}
+
+fn default_frame_rate() -> u32 {
+    30
 }
 
 #[derive(Serialize, Clone, Copy)]
 pub enum ExportResult {
     Done,
     ErrorBadFileType,
+    ErrorNoDiskSpace,
     ErrorUnknown,
+    Cancelled,
 }
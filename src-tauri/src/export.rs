@@ -0,0 +1,136 @@
+//! Renders export requests into files, picking
+//! the right pipeline for the requested file format
+
+use crate::{
+    checkpoint,
+    data::{ExportRequest, ExportResult, RenderParams},
+    field::DataFormat,
+    hdr, loops, metadata,
+    progress::Progress,
+    renderer::FractalImage,
+    streaming::{self, StreamFormat},
+    video,
+};
+use std::{fs, path::Path};
+
+/// Cancelled exports leave neither the file, nor its sidecar behind.
+/// Checkpoints are kept, so that the export can be resumed later
+fn remove_partial_files(path: &Path) {
+    let _ = fs::remove_file(path);
+    let _ = fs::remove_file(metadata::sidecar_path(path));
+}
+
+/// Exports cancelled only after their last row are kept as they are
+fn finish(path: &Path, result: ExportResult, progress: &Progress) -> ExportResult {
+    let stopped = matches!(result, ExportResult::Cancelled) || !progress.is_complete();
+    if !progress.is_cancelled() || !stopped {
+        return result;
+    }
+    remove_partial_files(path);
+    ExportResult::Cancelled
+}
+
+pub fn export_to_file(request: ExportRequest, progress: &Progress) -> ExportResult {
+    let path = request.filepath.clone();
+    let rows = request.fragment.height_px as u64;
+    let frames = request.animation.as_ref().map_or(1, loops::rendered_frames);
+    progress.expect_rows(rows * frames as u64);
+    let result = export(request, progress);
+    finish(Path::new(&path), result, progress)
+}
+
+/// Continues a checkpointed export, see checkpoint::resume_export
+pub fn resume_export(manifest: impl AsRef<Path>, progress: &Progress) -> ExportResult {
+    let Some(request) = checkpoint::read_manifest(&manifest) else {
+        return ExportResult::ErrorUnknown;
+    };
+    let path = request.filepath.clone();
+    let result = checkpoint::resume_export(request, &manifest, progress);
+    finish(Path::new(&path), result, progress)
+}
+
+fn export(request: ExportRequest, progress: &Progress) -> ExportResult {
+    let path = request.filepath.clone();
+    if let Some(animation) = &request.animation {
+        return loops::export_loop(&request, animation, progress);
+    }
+    let fractal = FractalImage::from(request.clone()).with_progress(progress.clone());
+    if let Some(format) = DataFormat::from_path(&path) {
+        let field = fractal.with_distance().render_field_parallel();
+        return match field.save(path, format) {
+            Err(_) => ExportResult::ErrorUnknown,
+            Ok(_) => ExportResult::Done,
+        };
+    }
+    if hdr::is_exr_path(&path) {
+        let color = request.color.clone().into();
+        let with_extras = request.extra_channels;
+        let fractal = match with_extras {
+            true => fractal.with_distance(),
+            false => fractal,
+        };
+        let field = fractal.render_field_parallel();
+        return match hdr::save_exr(path, &field, &color, with_extras) {
+            Err(_) => ExportResult::ErrorUnknown,
+            Ok(_) => ExportResult::Done,
+        };
+    }
+    if video::is_y4m_path(&path) {
+        let chroma = request.chroma;
+        let image = fractal.render_parallel();
+        return match video::save_y4m(&image, path, chroma) {
+            Err(_) => ExportResult::ErrorUnknown,
+            Ok(_) => ExportResult::Done,
+        };
+    }
+    if let Some(format) = StreamFormat::from_path(&path) {
+        if checkpoint::needs_checkpoints(&request) {
+            return checkpoint::export_with_checkpoints(request, progress);
+        }
+        return streaming::export_streamed(request, format, progress);
+    }
+    if image::ImageFormat::from_path(&path).is_err() {
+        return ExportResult::ErrorBadFileType;
+    }
+    let params = RenderParams::from(&request);
+    let quality = request.quality;
+    let image = fractal.render_parallel();
+    match metadata::save_with_params(&image, path, &params, quality) {
+        Err(_) => ExportResult::ErrorUnknown,
+        Ok(_) => ExportResult::Done,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::export_request;
+    use std::sync::{Arc, OnceLock};
+
+    #[test]
+    fn leaves_nothing_after_cancelling() {
+        let request = export_request("./cancelled.jpeg");
+        let progress = Progress::default();
+        progress.cancel();
+        let result = export_to_file(request, &progress);
+        assert!(matches!(result, ExportResult::Cancelled));
+        assert!(!Path::new("./cancelled.jpeg").exists());
+        assert!(!Path::new("./cancelled.jpeg.json").exists());
+    }
+
+    #[test]
+    fn keeps_exports_cancelled_after_last_row() {
+        let handle = Arc::new(OnceLock::<Progress>::new());
+        let cancelled = handle.clone();
+        let progress = Progress::new(move |fraction| {
+            if fraction == 1.0 {
+                cancelled.get().unwrap().cancel();
+            }
+        });
+        handle.set(progress.clone()).ok();
+        let result = export_to_file(export_request("./cancelled_late.jpeg"), &progress);
+        assert!(progress.is_cancelled());
+        assert!(matches!(result, ExportResult::Done));
+        assert!(Path::new("./cancelled_late.jpeg").exists());
+    }
+}
@@ -0,0 +1,204 @@
+//! Uncolored, per-pixel iteration data, for exports meant to be analysed
+//! (or recolored) outside of the app. Two file formats are supported:
+//!
+//! - `.npy` - NumPy array of shape (height, width, 5) and dtype `<f4`
+//! - `.raw` - the same array, prefixed with a JSON header:
+//!   - bytes 0..8: magic string `FRACTRAW`
+//!   - bytes 8..12: length of the header (N), u32 little endian
+//!   - bytes 12..12+N: UTF-8 JSON header with the image size,
+//!     channel names, dtype and the fragment of the complex plane
+//!   - the rest: width * height * 5 f32 little endian values
+//!
+//! In both formats rows go from the bottom of the fragment to its top
+//! (same as in the rendered images), and channels are interleaved
+//! in the order defined by CHANNELS.
+
+use crate::{
+    color::ColorCreator, data::FractalFragment, fractal::ComplexItem, renderer::ImageBuffer,
+};
+use serde_json::json;
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+pub const CHANNELS: [&str; 5] = ["smooth_index", "re", "im", "period", "distance"];
+const RAW_MAGIC: &[u8; 8] = b"FRACTRAW";
+const NPY_MAGIC: &[u8; 6] = b"\x93NUMPY";
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DataFormat {
+    Npy,
+    Raw,
+}
+
+impl DataFormat {
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?;
+        match extension.to_lowercase().as_str() {
+            "npy" => Some(Self::Npy),
+            "raw" => Some(Self::Raw),
+            _ => None,
+        }
+    }
+}
+
+pub struct IterationField {
+    pub fragment: FractalFragment,
+    pub items: Vec<ComplexItem>,
+}
+
+impl IterationField {
+    pub fn new(fragment: FractalFragment, items: Vec<ComplexItem>) -> Self {
+        Self { fragment, items }
+    }
+
+    /// Colors the field, without evaluating the fractal again
+    pub fn colorize(&self, color: &ColorCreator) -> ImageBuffer {
+        let pixels = self.items.iter().flat_map(|item| color.get_pixel(item).0);
+        let size = &self.fragment;
+        ImageBuffer::from_raw(size.width_px, size.height_px, pixels.collect()).unwrap()
+    }
+
+    fn channels(item: &ComplexItem) -> [f32; CHANNELS.len()] {
+        [
+            item.smooth_index() as f32,
+            item.value.re as f32,
+            item.value.im as f32,
+            item.period as f32,
+            item.distance as f32,
+        ]
+    }
+
+    fn write_data(&self, out: &mut impl Write) -> io::Result<()> {
+        for item in &self.items {
+            for value in Self::channels(item) {
+                out.write_all(&value.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn npy_header(&self) -> Vec<u8> {
+        let size = &self.fragment;
+        let dict = format!(
+            "{{'descr': '<f4', 'fortran_order': False, 'shape': ({}, {}, {}), }}",
+            size.height_px,
+            size.width_px,
+            CHANNELS.len()
+        );
+        // magic + version + header length take 10 bytes, the whole
+        // header has to be padded to a multiple of 64, ending with '\n'
+        let unpadded = NPY_MAGIC.len() + 4 + dict.len() + 1;
+        let padding = (64 - unpadded % 64) % 64;
+
+        let mut header = NPY_MAGIC.to_vec();
+        header.extend([1, 0]);
+        header.extend(((dict.len() + padding + 1) as u16).to_le_bytes());
+        header.extend(dict.bytes());
+        header.extend(std::iter::repeat(b' ').take(padding));
+        header.push(b'\n');
+        header
+    }
+
+    fn raw_header(&self) -> Vec<u8> {
+        let size = &self.fragment;
+        let header = json!({
+            "version": 1,
+            "width": size.width_px,
+            "height": size.height_px,
+            "channels": CHANNELS,
+            "dtype": "<f4",
+            "rows": "bottom_to_top",
+            "top_left": { "re": size.top_left.re, "im": size.top_left.im },
+            "bottom_right": { "re": size.bottom_right.re, "im": size.bottom_right.im },
+        })
+        .to_string();
+
+        let mut bytes = RAW_MAGIC.to_vec();
+        bytes.extend((header.len() as u32).to_le_bytes());
+        bytes.extend(header.bytes());
+        bytes
+    }
+
+    pub fn write_to(&self, out: &mut impl Write, format: DataFormat) -> io::Result<()> {
+        let header = match format {
+            DataFormat::Npy => self.npy_header(),
+            DataFormat::Raw => self.raw_header(),
+        };
+        out.write_all(&header)?;
+        self.write_data(out)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>, format: DataFormat) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        self.write_to(&mut file, format)?;
+        file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{data::FractalVariant, fractal::Fractal};
+    use num::complex::Complex64;
+
+    fn field() -> IterationField {
+        let fractal = Fractal::new(64, FractalVariant::Mandelbrot).with_distance();
+        let items = [-0.5, 0.3, 2.0]
+            .map(|re| fractal.eval(Complex64::new(re, 0.0)))
+            .to_vec();
+        let fragment = FractalFragment {
+            width_px: 3,
+            height_px: 1,
+            top_left: Complex64::new(-0.5, 0.0),
+            bottom_right: Complex64::new(2.5, 0.0),
+        };
+        IterationField::new(fragment, items)
+    }
+
+    #[test]
+    fn detects_formats() {
+        assert_eq!(DataFormat::from_path("a/b.npy"), Some(DataFormat::Npy));
+        assert_eq!(DataFormat::from_path("a/b.RAW"), Some(DataFormat::Raw));
+        assert_eq!(DataFormat::from_path("a/b.png"), None);
+    }
+
+    #[test]
+    fn pads_npy_header() {
+        let mut buffer = vec![];
+        field().write_to(&mut buffer, DataFormat::Npy).unwrap();
+        let header_len = u16::from_le_bytes([buffer[8], buffer[9]]) as usize;
+        assert_eq!((10 + header_len) % 64, 0);
+        assert_eq!(buffer[10 + header_len - 1], b'\n');
+        assert_eq!(buffer.len(), 10 + header_len + 3 * CHANNELS.len() * 4);
+    }
+
+    #[test]
+    fn writes_raw_header() {
+        let mut buffer = vec![];
+        field().write_to(&mut buffer, DataFormat::Raw).unwrap();
+        assert_eq!(&buffer[..8], RAW_MAGIC);
+        let header_len = u32::from_le_bytes(buffer[8..12].try_into().unwrap()) as usize;
+        let header: serde_json::Value =
+            serde_json::from_slice(&buffer[12..12 + header_len]).unwrap();
+        assert_eq!(header["width"], 3);
+        assert_eq!(buffer.len(), 12 + header_len + 3 * CHANNELS.len() * 4);
+    }
+
+    #[test]
+    fn estimates_distance_outside_only() {
+        let field = field();
+        assert_eq!(field.items[0].distance, 0.0);
+        assert!(field.items[2].distance > 0.0);
+    }
+
+    #[test]
+    fn colorizes_like_renderer() {
+        let color: ColorCreator = crate::fixtures::color_config().into();
+        let fractal = Fractal::new(64, FractalVariant::Mandelbrot);
+        let image = crate::renderer::FractalImage::new(fractal, field().fragment, color);
+        assert_eq!(image.render_field().colorize(&color), image.render());
+    }
+}
@@ -0,0 +1,118 @@
+//! Configs shared by the tests, all of them of the Mandelbrot set,
+//! and an allocator measuring the memory taken by parts of them
+
+use crate::{
+    data::{
+        Chroma, ColorConfig, ColorHex, ColorMethod, ExportRequest, FractalConfig, FractalFragment,
+        FractalVariant,
+    },
+    fractal::CYCLE_TOLERANCE,
+    renderer::FractalImage,
+};
+use num::complex::Complex64;
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+pub fn fractal_config() -> FractalConfig {
+    FractalConfig {
+        variant: FractalVariant::Mandelbrot,
+        max_iterations: 64,
+        cycle_tolerance: CYCLE_TOLERANCE,
+    }
+}
+
+pub fn color_config() -> ColorConfig {
+    ColorConfig {
+        color: ColorHex {
+            hex_start: "#ff0000".to_owned(),
+            hex_end: "#ffff00".to_owned(),
+        },
+        brightness: 1.0,
+        anti_alias: true,
+        method: ColorMethod::Linear,
+    }
+}
+
+/// 40 x 30 pixels of the whole set
+pub fn export_request(filepath: &str) -> ExportRequest {
+    ExportRequest {
+        fractal: fractal_config(),
+        fragment: FractalFragment {
+            width_px: 40,
+            height_px: 30,
+            top_left: Complex64::new(-2.0, 1.0),
+            bottom_right: Complex64::new(1.0, -1.25),
+        },
+        color: color_config(),
+        filepath: filepath.to_owned(),
+        extra_channels: false,
+        quality: 75,
+        chroma: Chroma::C420,
+        animation: None,
+        strategy: Default::default(),
+    }
+}
+
+/// Image of the Mandelbrot set between the corners
+pub fn image(
+    width_px: u32,
+    height_px: u32,
+    top_left: Complex64,
+    bottom_right: Complex64,
+) -> FractalImage {
+    let fragment = FractalFragment {
+        width_px,
+        height_px,
+        top_left,
+        bottom_right,
+    };
+    FractalImage::new(fractal_config().into(), fragment, color_config().into())
+}
+
+thread_local! {
+    /// Bytes allocated by the thread since it started measuring, and their peak
+    static MEASURED: Cell<Option<(isize, isize)>> = const { Cell::new(None) };
+}
+
+fn measure(bytes: isize) {
+    let _ = MEASURED.try_with(|measured| {
+        if let Some((current, peak)) = measured.get() {
+            measured.set(Some((current + bytes, peak.max(current + bytes))));
+        }
+    });
+}
+
+/// The system allocator, counting what measuring threads allocate
+struct Measuring;
+
+#[global_allocator]
+static ALLOCATOR: Measuring = Measuring;
+
+unsafe impl GlobalAlloc for Measuring {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        measure(layout.size() as isize);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        measure(-(layout.size() as isize));
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        measure(new_size as isize - layout.size() as isize);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+/// Peak of the bytes allocated by this thread while running `work`.
+/// Other threads aren't counted, like the render pool, whose blocks
+/// are handed over to the thread, which puts them together
+pub fn peak_allocation<T>(work: impl FnOnce() -> T) -> (T, usize) {
+    MEASURED.with(|measured| measured.set(Some((0, 0))));
+    let result = work();
+    let (_, peak) = MEASURED.with(|measured| measured.take()).unwrap();
+    (result, peak as usize)
+}
@@ -1,19 +1,37 @@
-use crate::data::FractalVariant;
+use crate::{data::FractalVariant, renderer::Filling, symmetry::Symmetry};
 use num::complex::Complex64;
 
+#[derive(Clone, Copy)]
 pub struct ComplexItem {
     pub index: f64,
     pub max_index: f64,
+    pub period: f64,
     // Those^ values will eventually be converted to f64,
     // so for simplicity let's do it once, in Fractal::eval
     pub value: Complex64,
+    /// Exterior distance estimate, only computed
+    /// for fractals created with Fractal::with_distance
+    pub distance: f64,
 }
 
+/// Orbits coming back this close to an earlier point count as cycles.
+/// Deep zooms may need a smaller one, below the size of their pixels
+pub const CYCLE_TOLERANCE: f64 = 1e-12;
+
 #[derive(Clone)]
 pub struct Fractal {
     max_item_id: u32,
     constant: Option<Complex64>,
     next_item: fn(&Fractal, Complex64, &Complex64) -> Complex64,
+    next_derivative: Option<fn(Complex64, Complex64) -> Complex64>,
+    track_distance: bool,
+    symmetry: Option<Symmetry>,
+    /// Points of the main cardioid and the period-2 bulb
+    /// are known to belong to the set, without iterating
+    skips_main_bulbs: bool,
+    cycle_tolerance: f64,
+    /// The most filling, which can't fill pixels of other areas
+    filling: Filling,
 }
 
 impl Fractal {
@@ -32,6 +50,70 @@ impl Fractal {
                 Newton => Self::next_in_newton,
                 JuliaSet { constant: _ } => Self::next_in_julia,
             },
+            next_derivative: match variant {
+                BurningShip | Mandelbrot => Some(Self::derivative_in_mandelbrot),
+                JuliaSet { constant: _ } => Some(Self::derivative_in_julia),
+                Newton => None,
+            },
+            track_distance: false,
+            // Burning Ship folds its orbits with abs(), which breaks symmetry
+            symmetry: match variant {
+                Mandelbrot | Newton => Some(Symmetry::RealAxis),
+                JuliaSet { constant: _ } => Some(Symmetry::Origin),
+                BurningShip => None,
+            },
+            skips_main_bulbs: matches!(variant, Mandelbrot),
+            cycle_tolerance: CYCLE_TOLERANCE,
+            // Escape bands of the Mandelbrot set are rings around it. Filled
+            // Julia sets have no holes, but their bands can break up around
+            // their pieces. Areas of the others can enclose anything
+            filling: match variant {
+                Mandelbrot => Filling::Uniform,
+                JuliaSet { constant: _ } => Filling::Interior,
+                BurningShip | Newton => Filling::Off,
+            },
+        }
+    }
+
+    /// Makes eval track the derivative of each orbit, so that
+    /// ComplexItem::distance can be filled. Slows down eval noticeably.
+    pub fn with_distance(self) -> Self {
+        Self {
+            track_distance: true,
+            ..self
+        }
+    }
+
+    /// Zero finds only exact cycles, larger tolerances find cycles
+    /// sooner, but may mistake slowly escaping points for members
+    pub fn with_cycle_tolerance(self, cycle_tolerance: f64) -> Self {
+        Self {
+            cycle_tolerance,
+            ..self
+        }
+    }
+
+    /// Period of the attracting cycle of points in the main
+    /// cardioid (1) and in the period-2 bulb (2) of the Mandelbrot set
+    fn main_bulb_period(point: &Complex64) -> Option<u32> {
+        let x = point.re - 0.25;
+        let y2 = point.im * point.im;
+        let q = x * x + y2;
+        if q * (q + x) <= 0.25 * y2 {
+            return Some(1);
+        }
+        let x = point.re + 1.0;
+        Some(2).filter(|_| x * x + y2 <= 1.0 / 16.0)
+    }
+
+    /// Point of the attracting cycle, which orbits in the bulb end up in
+    fn main_bulb_cycle(period: u32, point: Complex64) -> Complex64 {
+        let one = Complex64::new(1.0, 0.0);
+        match period {
+            // Fixed point of z^2 + c
+            1 => (one - (one - 4.0 * point).sqrt()) / 2.0,
+            // Root of z^2 + z + c + 1, both of them form the 2-cycle
+            _ => ((-3.0 - 4.0 * point).sqrt() - one) / 2.0,
         }
     }
 
@@ -54,28 +136,88 @@ impl Fractal {
         nominator / denominator
     }
 
+    fn derivative_in_mandelbrot(current_item: Complex64, derivative: Complex64) -> Complex64 {
+        current_item * derivative * 2.0 + 1.0
+    }
+
+    fn derivative_in_julia(current_item: Complex64, derivative: Complex64) -> Complex64 {
+        current_item * derivative * 2.0
+    }
+
     fn in_bounds(&self, point: &Complex64) -> bool {
         let distance = point.re * point.re + point.im * point.im;
         distance < Self::ESCAPE_RADIUS_POW2
     }
 
+    fn distance(&self, current_item: &Complex64, derivative: &Complex64) -> f64 {
+        if self.in_bounds(current_item) {
+            return 0.0; // Point belongs to the set
+        }
+        let norm = current_item.norm();
+        0.5 * norm * norm.ln() / derivative.norm()
+    }
+
+    pub fn symmetry(&self) -> Option<Symmetry> {
+        self.symmetry
+    }
+
+    pub fn filling(&self) -> Filling {
+        self.filling
+    }
+
+    /// Stands in for points, which were never evaluated (in cancelled renders)
+    pub fn unevaluated(&self) -> ComplexItem {
+        ComplexItem {
+            value: Complex64::default(),
+            index: 0.0,
+            max_index: self.max_item_id as f64,
+            period: 0.0,
+            distance: f64::NAN,
+        }
+    }
+
     pub fn eval(&self, point: Complex64) -> ComplexItem {
+        let next_derivative = self.next_derivative.filter(|_| self.track_distance);
+        if let Some(period) = Self::main_bulb_period(&point).filter(|_| self.skips_main_bulbs) {
+            return ComplexItem {
+                value: Self::main_bulb_cycle(period, point),
+                index: self.max_item_id as f64,
+                max_index: self.max_item_id as f64,
+                period: period as f64,
+                distance: match next_derivative {
+                    Some(_) => 0.0,
+                    None => f64::NAN,
+                },
+            };
+        }
+
+        // Brent's cycle detection: the orbit is compared with a point saved
+        // at every power of two iterations, so cycles of any length are found
         let mut item_id = 0;
         let mut period = 0;
+        let mut power = 1;
+        let mut cycle = 0;
         let mut current_item = point;
         let mut old_item = point;
+        let mut derivative = Complex64::new(1.0, 0.0);
+        let tolerance = self.cycle_tolerance * self.cycle_tolerance;
         while self.in_bounds(&current_item) && item_id < self.max_item_id {
+            if let Some(next_derivative) = next_derivative {
+                derivative = next_derivative(current_item, derivative);
+            }
             current_item = (self.next_item)(self, current_item, &point);
             item_id += 1;
             period += 1;
 
-            if current_item == old_item {
+            if (current_item - old_item).norm_sqr() <= tolerance {
                 item_id = self.max_item_id;
+                cycle = period;
                 break;
             }
 
-            if period >= 20 {
+            if period == power {
                 old_item = current_item;
+                power *= 2;
                 period = 0;
             }
         }
@@ -83,6 +225,61 @@ impl Fractal {
             value: current_item,
             index: item_id as f64,
             max_index: self.max_item_id as f64,
+            period: cycle as f64,
+            distance: match next_derivative {
+                Some(_) => self.distance(&current_item, &derivative),
+                None => f64::NAN,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn iterated() -> Fractal {
+        Fractal {
+            skips_main_bulbs: false,
+            ..Fractal::new(2000, FractalVariant::Mandelbrot)
+        }
+    }
+
+    #[test]
+    fn skips_only_members_of_main_bulbs() {
+        let fractal = Fractal::new(2000, FractalVariant::Mandelbrot);
+        for re in -40..=10 {
+            for im in -15..=15 {
+                let point = Complex64::new(re as f64 * 0.05, im as f64 * 0.05);
+                if Fractal::main_bulb_period(&point).is_some() {
+                    assert_eq!(iterated().eval(point).index, 2000.0, "{point}");
+                }
+                assert_eq!(fractal.eval(point).index, iterated().eval(point).index);
+            }
         }
     }
+
+    #[test]
+    fn ends_skipped_orbits_in_their_cycles() {
+        let (fractal, iterated) = (Fractal::new(2000, FractalVariant::Mandelbrot), iterated());
+        for point in [Complex64::new(-0.1, 0.1), Complex64::new(-1.0, 0.05)] {
+            let value = fractal.eval(point).value;
+            let cycle = [value, value * value + point];
+            let ended = iterated.eval(point).value;
+            assert!(cycle.iter().any(|member| (member - ended).norm() < 1e-5));
+        }
+    }
+
+    #[test]
+    fn finds_cycle_periods() {
+        let fractal = iterated();
+        let inside = |re, im| fractal.eval(Complex64::new(re, im));
+        assert_eq!(inside(-0.1, 0.1).period, 1.0);
+        assert_eq!(inside(-1.0, 0.05).period, 2.0);
+        assert_eq!(inside(-0.12, 0.75).period, 3.0);
+        assert!(inside(-0.12, 0.75).index == 2000.0);
+
+        let exact = iterated().with_cycle_tolerance(0.0);
+        assert_eq!(exact.eval(Complex64::new(-0.12, 0.75)).index, 2000.0);
+    }
 }
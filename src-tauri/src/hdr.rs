@@ -0,0 +1,89 @@
+//! 32-bit float OpenEXR exports. Color channels hold linear light,
+//! before being clipped to u8, so that renders can be re-graded later.
+
+use crate::{color::ColorCreator, field::IterationField};
+use exr::prelude::*;
+use std::path::Path;
+
+pub fn is_exr_path(path: impl AsRef<Path>) -> bool {
+    let extension = path.as_ref().extension().and_then(|ext| ext.to_str());
+    extension.is_some_and(|ext| ext.eq_ignore_ascii_case("exr"))
+}
+
+fn channel(name: &str, samples: Vec<f32>) -> AnyChannel<FlatSamples> {
+    AnyChannel::new(name, FlatSamples::F32(samples))
+}
+
+/// Extra channels (smooth_index, distance) are only written
+/// when `with_extras` is set. For a meaningful distance channel,
+/// the field should be rendered with FractalImage::with_distance
+pub fn save_exr(
+    path: impl AsRef<Path>,
+    field: &IterationField,
+    color: &ColorCreator,
+    with_extras: bool,
+) -> Result<()> {
+    let pixel_count = field.items.len();
+    let mut rgb = [(); 3].map(|_| Vec::with_capacity(pixel_count));
+    for item in &field.items {
+        let pixel = color.get_linear_pixel(item);
+        for (channel, value) in rgb.iter_mut().zip(pixel) {
+            channel.push(value);
+        }
+    }
+
+    let [red, green, blue] = rgb;
+    let mut channels = SmallVec::new();
+    channels.push(channel("R", red));
+    channels.push(channel("G", green));
+    channels.push(channel("B", blue));
+    if with_extras {
+        let smooth = field.items.iter().map(|i| i.smooth_index() as f32);
+        let distance = field.items.iter().map(|i| i.distance as f32);
+        channels.push(channel("smooth_index", smooth.collect()));
+        channels.push(channel("distance", distance.collect()));
+    }
+
+    let size = &field.fragment;
+    let layer = Layer::new(
+        (size.width_px as usize, size.height_px as usize),
+        LayerAttributes::named("fractal"),
+        Encoding::SMALL_LOSSLESS,
+        AnyChannels::sort(channels),
+    );
+    Image::from_layer(layer).write().to_file(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{data::*, fixtures, fractal::Fractal, renderer::FractalImage};
+    use num::complex::Complex64;
+
+    #[test]
+    fn detects_exr_paths() {
+        assert!(is_exr_path("render.exr"));
+        assert!(is_exr_path("render.EXR"));
+        assert!(!is_exr_path("render.png"));
+    }
+
+    #[test]
+    fn saves_exr_with_extras() {
+        let color: ColorCreator = fixtures::color_config().into();
+        let fragment = FractalFragment {
+            width_px: 64,
+            height_px: 32,
+            top_left: Complex64::new(-2.0, 1.0),
+            bottom_right: Complex64::new(2.0, -1.0),
+        };
+        let fractal = Fractal::new(64, FractalVariant::Mandelbrot);
+        let field = FractalImage::new(fractal, fragment, color)
+            .with_distance()
+            .render_field();
+        save_exr("./linear.exr", &field, &color, true).unwrap();
+
+        let image = read_all_flat_layers_from_file("./linear.exr").unwrap();
+        let channels = &image.layer_data[0].channel_data.list;
+        assert_eq!(channels.len(), 5);
+    }
+}
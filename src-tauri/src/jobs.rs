@@ -0,0 +1,322 @@
+//! Exports run as jobs in a queue. At most `concurrency` of them render
+//! at the same time, the others wait for their turn in the order they
+//! were enqueued. Every change of a job is passed to the listener.
+
+use crate::{
+    checkpoint,
+    data::{ExportRequest, ExportResult},
+    export,
+    progress::Progress,
+};
+use serde::Serialize;
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+};
+
+pub type JobId = u32;
+/// Finished jobs remembered for list_exports, older ones are forgotten
+pub const MAX_FINISHED_JOBS: usize = 32;
+
+#[derive(Clone)]
+pub enum JobTask {
+    Export(Box<ExportRequest>),
+    /// Continues a checkpointed export, described by the manifest
+    Resume {
+        manifest: String,
+    },
+}
+
+impl JobTask {
+    fn filepath(&self) -> String {
+        match self {
+            Self::Export(request) => request.filepath.clone(),
+            Self::Resume { manifest } => match checkpoint::read_manifest(manifest) {
+                Some(request) => request.filepath,
+                None => manifest.clone(),
+            },
+        }
+    }
+
+    fn run(self, progress: &Progress) -> ExportResult {
+        match self {
+            Self::Export(request) => export::export_to_file(*request, progress),
+            Self::Resume { manifest } => export::resume_export(manifest, progress),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(tag = "state", rename_all = "camelCase")]
+pub enum JobStatus {
+    Queued,
+    Running { progress: f64 },
+    Finished { result: ExportResult },
+}
+
+#[derive(Serialize, Clone)]
+pub struct JobInfo {
+    pub id: JobId,
+    pub filepath: String,
+    pub status: JobStatus,
+}
+
+enum JobState {
+    Queued(JobTask),
+    Running,
+    Finished(ExportResult),
+}
+
+struct Job {
+    id: JobId,
+    filepath: String,
+    state: JobState,
+    progress: Progress,
+}
+
+impl Job {
+    fn info(&self) -> JobInfo {
+        let status = match self.state {
+            JobState::Queued(_) => JobStatus::Queued,
+            JobState::Running => JobStatus::Running {
+                progress: self.progress.fraction(),
+            },
+            JobState::Finished(result) => JobStatus::Finished { result },
+        };
+        JobInfo {
+            id: self.id,
+            filepath: self.filepath.clone(),
+            status,
+        }
+    }
+}
+
+struct Jobs {
+    jobs: Vec<Job>,
+    next_id: JobId,
+    concurrency: usize,
+}
+
+impl Jobs {
+    /// Forgets the oldest finished jobs, beyond the most recent ones
+    fn prune(&mut self) {
+        let is_finished = |job: &Job| matches!(job.state, JobState::Finished(_));
+        let finished = self.jobs.iter().filter(|job| is_finished(job)).count();
+        let mut excess = finished.saturating_sub(MAX_FINISHED_JOBS);
+        self.jobs.retain(|job| {
+            let forget = excess > 0 && is_finished(job);
+            excess -= forget as usize;
+            !forget
+        });
+    }
+}
+
+type Listener = Arc<dyn Fn(&JobInfo) + Send + Sync>;
+
+#[derive(Clone)]
+pub struct ExportQueue {
+    jobs: Arc<Mutex<Jobs>>,
+    listener: Listener,
+}
+
+impl ExportQueue {
+    /// Concurrency of 0 keeps the queue paused
+    pub fn new(concurrency: usize, listener: impl Fn(&JobInfo) + Send + Sync + 'static) -> Self {
+        let jobs = Jobs {
+            jobs: Vec::new(),
+            next_id: 0,
+            concurrency,
+        };
+        Self {
+            jobs: Arc::new(Mutex::new(jobs)),
+            listener: Arc::new(listener),
+        }
+    }
+
+    pub fn enqueue(&self, task: JobTask) -> JobId {
+        // Resumed jobs read their manifest, so not under the lock
+        let filepath = task.filepath();
+        let mut jobs = self.jobs.lock().unwrap();
+        let id = jobs.next_id;
+        jobs.next_id += 1;
+
+        let listener = self.listener.clone();
+        let reported = filepath.clone();
+        let progress = Progress::new(move |progress| {
+            listener(&JobInfo {
+                id,
+                filepath: reported.clone(),
+                status: JobStatus::Running { progress },
+            })
+        });
+        let job = Job {
+            id,
+            filepath,
+            state: JobState::Queued(task),
+            progress,
+        };
+        let queued = job.info();
+        jobs.jobs.push(job);
+        drop(jobs);
+        (self.listener)(&queued);
+        self.start_waiting();
+        id
+    }
+
+    pub fn list(&self) -> Vec<JobInfo> {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.jobs.iter().map(Job::info).collect()
+    }
+
+    pub fn status(&self, id: JobId) -> Option<JobStatus> {
+        let jobs = self.jobs.lock().unwrap();
+        let job = jobs.jobs.iter().find(|job| job.id == id)?;
+        Some(job.info().status)
+    }
+
+    /// Returns false, when the job is unknown or already finished
+    pub fn cancel(&self, id: JobId) -> bool {
+        let mut jobs = self.jobs.lock().unwrap();
+        let Some(job) = jobs.jobs.iter_mut().find(|job| job.id == id) else {
+            return false;
+        };
+        match job.state {
+            JobState::Queued(_) => {
+                job.state = JobState::Finished(ExportResult::Cancelled);
+                let cancelled = job.info();
+                jobs.prune();
+                drop(jobs);
+                (self.listener)(&cancelled);
+                true
+            }
+            // The job finishes on its own, once the render stops
+            JobState::Running => {
+                job.progress.cancel();
+                true
+            }
+            JobState::Finished(_) => false,
+        }
+    }
+
+    pub fn set_concurrency(&self, concurrency: usize) {
+        self.jobs.lock().unwrap().concurrency = concurrency;
+        self.start_waiting();
+    }
+
+    fn finish(&self, id: JobId, result: ExportResult) {
+        let mut jobs = self.jobs.lock().unwrap();
+        let finished = jobs.jobs.iter_mut().find(|job| job.id == id).map(|job| {
+            job.state = JobState::Finished(result);
+            job.info()
+        });
+        jobs.prune();
+        drop(jobs);
+        if let Some(finished) = finished {
+            (self.listener)(&finished);
+        }
+        self.start_waiting();
+    }
+
+    /// Starts queued jobs, as long as the concurrency allows
+    fn start_waiting(&self) {
+        let mut started = vec![];
+        let mut jobs = self.jobs.lock().unwrap();
+        loop {
+            let running = jobs
+                .jobs
+                .iter()
+                .filter(|job| matches!(job.state, JobState::Running))
+                .count();
+            if running >= jobs.concurrency {
+                break;
+            }
+            let waiting = jobs
+                .jobs
+                .iter_mut()
+                .find(|job| matches!(job.state, JobState::Queued(_)));
+            let Some(job) = waiting else {
+                break;
+            };
+            let JobState::Queued(task) = std::mem::replace(&mut job.state, JobState::Running)
+            else {
+                unreachable!()
+            };
+            started.push((job.info(), task, job.progress.clone()));
+        }
+        drop(jobs);
+
+        for (info, task, progress) in started {
+            (self.listener)(&info);
+            let queue = self.clone();
+            thread::spawn(move || {
+                let result = task.run(&progress);
+                queue.finish(info.id, result);
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::export_request;
+    use std::{collections::HashSet, sync::mpsc};
+
+    #[test]
+    fn runs_jobs_one_at_a_time() {
+        let (sender, receiver) = mpsc::channel();
+        // Paused, until both jobs are in
+        let queue = ExportQueue::new(0, move |job| sender.send(job.clone()).unwrap());
+        let first = queue.enqueue(JobTask::Export(Box::new(export_request("./queued_1.png"))));
+        let second = queue.enqueue(JobTask::Export(Box::new(export_request("./queued_2.png"))));
+        assert!(matches!(queue.status(second), Some(JobStatus::Queued)));
+        queue.set_concurrency(1);
+
+        let mut finished = vec![];
+        let mut running = HashSet::new();
+        while finished.len() < 2 {
+            let job = receiver.recv().unwrap();
+            match job.status {
+                JobStatus::Queued => (),
+                JobStatus::Running { .. } => _ = running.insert(job.id),
+                JobStatus::Finished { result } => {
+                    assert!(matches!(result, ExportResult::Done));
+                    finished.push(job.id);
+                    running.remove(&job.id);
+                }
+            }
+            assert!(running.len() <= 1);
+        }
+        assert_eq!(finished, [first, second]);
+        assert_eq!(queue.list().len(), 2);
+    }
+
+    #[test]
+    fn cancels_queued_job() {
+        let queue = ExportQueue::new(0, |_| {});
+        let id = queue.enqueue(JobTask::Export(Box::new(export_request("./never.png"))));
+        assert!(queue.cancel(id));
+        assert!(!queue.cancel(id));
+        assert!(matches!(
+            queue.status(id),
+            Some(JobStatus::Finished {
+                result: ExportResult::Cancelled
+            })
+        ));
+        queue.set_concurrency(1);
+        assert!(!std::path::Path::new("./never.png").exists());
+    }
+
+    #[test]
+    fn forgets_oldest_finished_jobs() {
+        let queue = ExportQueue::new(0, |_| {});
+        let ids: Vec<_> = (0..MAX_FINISHED_JOBS + 2)
+            .map(|_| queue.enqueue(JobTask::Export(Box::new(export_request("./never.png")))))
+            .collect();
+        for &id in &ids {
+            queue.cancel(id);
+        }
+        let listed: Vec<_> = queue.list().iter().map(|job| job.id).collect();
+        assert_eq!(listed, ids[2..]);
+    }
+}
@@ -0,0 +1,378 @@
+//! Short, looping animations of a single view - exported as animated
+//! GIFs (quantized to 256 colors per frame), APNGs (lossless),
+//! Y4M streams, or PNG sequences (when the path has no extension).
+
+use crate::{
+    animation::frame_path,
+    color::ColorCreator,
+    data::{
+        ConstantPath, ExportRequest, ExportResult, FractalFragment, FractalVariant, LoopAnimation,
+        LoopMotion,
+    },
+    progress::Progress,
+    renderer::{FractalImage, ImageBuffer},
+    video::{self, Y4mWriter},
+};
+use color_quant::NeuQuant;
+use image::{
+    buffer::ConvertBuffer,
+    imageops::{dither, index_colors},
+    RgbaImage,
+};
+use num::complex::Complex64;
+use std::{
+    error::Error,
+    f64::consts::TAU,
+    fs::{self, File},
+    io::BufWriter,
+    path::Path,
+};
+
+enum LoopFormat {
+    Gif,
+    Apng,
+    Y4m,
+    Frames,
+}
+
+impl LoopFormat {
+    fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        let path = path.as_ref();
+        if video::is_y4m_path(path) {
+            return Some(Self::Y4m);
+        }
+        let Some(extension) = path.extension() else {
+            return Some(Self::Frames);
+        };
+        match extension.to_str()?.to_lowercase().as_str() {
+            "gif" => Some(Self::Gif),
+            "png" | "apng" => Some(Self::Apng),
+            _ => None,
+        }
+    }
+}
+
+impl ConstantPath {
+    /// Open paths end where they started only when their last point is the first one
+    fn is_closed(&self) -> bool {
+        match self {
+            Self::Polyline { points } => match (points.first(), points.last()) {
+                (Some(first), Some(last)) => first.0 == last.0,
+                _ => true,
+            },
+            Self::Circle { .. } | Self::Cardioid => true,
+        }
+    }
+
+    /// Point at progress 0.0 - 1.0 along the path
+    fn at(&self, progress: f64) -> Complex64 {
+        match self {
+            Self::Circle { center, radius } => {
+                center + Complex64::from_polar(*radius, TAU * progress)
+            }
+            Self::Cardioid => {
+                let turn = Complex64::from_polar(1.0, TAU * progress);
+                turn / 2.0 - turn * turn / 4.0
+            }
+            Self::Polyline { points } => {
+                let length: f64 = points
+                    .windows(2)
+                    .map(|pair| (pair[1].0 - pair[0].0).norm())
+                    .sum();
+                let mut left = length * progress;
+                for pair in points.windows(2) {
+                    let (from, to) = (pair[0].0, pair[1].0);
+                    let segment = (to - from).norm();
+                    if left <= segment && segment > 0.0 {
+                        return from + (to - from) * (left / segment);
+                    }
+                    left -= segment;
+                }
+                points.last().map_or(Complex64::default(), |point| point.0)
+            }
+        }
+    }
+}
+
+/// Scales the fragment around its center
+fn zoom(fragment: &FractalFragment, scale: f64) -> FractalFragment {
+    let center = (fragment.top_left + fragment.bottom_right) / 2.0;
+    FractalFragment {
+        top_left: center + (fragment.top_left - center) * scale,
+        bottom_right: center + (fragment.bottom_right - center) * scale,
+        ..fragment.clone()
+    }
+}
+
+/// Frame of the loop, at progress 0.0 - 1.0
+fn frame_image(request: &ExportRequest, motion: &LoopMotion, progress: f64) -> FractalImage {
+    let mut fractal = request.fractal.clone();
+    let mut fragment = request.fragment.clone();
+    let color = ColorCreator::from(request.color.clone());
+    match motion {
+        LoopMotion::JuliaMorph { path } => {
+            let constant = path.at(progress);
+            fractal.variant = FractalVariant::JuliaSet { constant };
+        }
+        // Recolored by cycle_palette
+        LoopMotion::PaletteCycle => {}
+        LoopMotion::Zoom { factor } => fragment = zoom(&fragment, factor.powf(-progress)),
+    };
+    FractalImage::new(fractal.into(), fragment, color)
+}
+
+/// Number of frames, for which the fractal has to be evaluated
+pub fn rendered_frames(animation: &LoopAnimation) -> u32 {
+    match animation.motion {
+        LoopMotion::PaletteCycle => 1,
+        _ => animation.frames,
+    }
+}
+
+fn render_frames(
+    request: &ExportRequest,
+    animation: &LoopAnimation,
+    progress: &Progress,
+) -> Vec<ImageBuffer> {
+    if let LoopMotion::PaletteCycle = animation.motion {
+        return cycle_palette(request, animation, progress);
+    }
+    // Open paths have to reach their ends, loops skip
+    // the last frame, as it would repeat the first one
+    let steps = match &animation.motion {
+        LoopMotion::JuliaMorph { path } if !path.is_closed() => animation.frames - 1,
+        _ => animation.frames,
+    };
+    (0..animation.frames)
+        .map(|frame| {
+            let at = frame as f64 / steps.max(1) as f64;
+            let image = frame_image(request, &animation.motion, at);
+            image.with_progress(progress.clone()).render_parallel()
+        })
+        .collect()
+}
+
+/// Iterations are computed once, only the colors change between frames
+fn cycle_palette(
+    request: &ExportRequest,
+    animation: &LoopAnimation,
+    progress: &Progress,
+) -> Vec<ImageBuffer> {
+    let image = frame_image(request, &animation.motion, 0.0);
+    let field = image
+        .with_progress(progress.clone())
+        .render_field_parallel();
+    let color = ColorCreator::from(request.color.clone());
+    (0..animation.frames)
+        .map(|frame| {
+            let progress = frame as f64 / animation.frames as f64;
+            // ping_pong takes 2.0 to get back to the start
+            field.colorize(&color.with_palette_offset(2.0 * progress))
+        })
+        .collect()
+}
+
+/// Palette and indexed pixels of the frame
+fn quantize(frame: &ImageBuffer, with_dither: bool) -> (Vec<u8>, Vec<u8>) {
+    let mut rgba: RgbaImage = frame.convert();
+    let palette = NeuQuant::new(10, 256, rgba.as_raw());
+    if with_dither {
+        dither(&mut rgba, &palette);
+    }
+    let indices = index_colors(&rgba, &palette).into_raw();
+    (palette.color_map_rgb(), indices)
+}
+
+fn save_gif(
+    frames: &[ImageBuffer],
+    path: &Path,
+    animation: &LoopAnimation,
+) -> Result<(), Box<dyn Error>> {
+    let (width, height) = frames[0].dimensions();
+    let (width, height) = (u16::try_from(width)?, u16::try_from(height)?);
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = gif::Encoder::new(file, width, height, &[])?;
+    encoder.set_repeat(gif::Repeat::Infinite)?;
+    for frame in frames {
+        let (palette, indices) = quantize(frame, animation.dither);
+        let mut frame = gif::Frame::from_palette_pixels(width, height, &indices, &palette, None);
+        // GIF delays are in hundredths of a second
+        frame.delay = (animation.delay_ms / 10).clamp(1, u16::MAX as u32) as u16;
+        encoder.write_frame(&frame)?;
+    }
+    Ok(())
+}
+
+fn save_apng(
+    frames: &[ImageBuffer],
+    path: &Path,
+    animation: &LoopAnimation,
+) -> Result<(), Box<dyn Error>> {
+    let (width, height) = frames[0].dimensions();
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(frames.len() as u32, 0)?;
+    encoder.set_frame_delay(animation.delay_ms.min(u16::MAX as u32) as u16, 1000)?;
+    let mut writer = encoder.write_header()?;
+    for frame in frames {
+        writer.write_image_data(frame.as_raw())?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+fn save_y4m(
+    frames: &[ImageBuffer],
+    path: &Path,
+    request: &ExportRequest,
+    animation: &LoopAnimation,
+) -> Result<(), Box<dyn Error>> {
+    let (width, height) = frames[0].dimensions();
+    let frame_rate = (1000.0 / animation.delay_ms.max(1) as f64).round().max(1.0);
+    let writer = video::open(path)?;
+    let mut stream = Y4mWriter::new(writer, width, height, frame_rate as u32, request.chroma)?;
+    for frame in frames {
+        stream.write_frame(frame)?;
+    }
+    stream.finish()?;
+    Ok(())
+}
+
+fn save_frames(frames: &[ImageBuffer], directory: &Path) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(directory)?;
+    for (id, frame) in frames.iter().enumerate() {
+        frame.save(frame_path(directory, id))?;
+    }
+    Ok(())
+}
+
+pub fn export_loop(
+    request: &ExportRequest,
+    animation: &LoopAnimation,
+    progress: &Progress,
+) -> ExportResult {
+    let path = Path::new(&request.filepath);
+    let Some(format) = LoopFormat::from_path(path) else {
+        return ExportResult::ErrorBadFileType;
+    };
+    if animation.frames == 0 {
+        return ExportResult::ErrorUnknown;
+    }
+
+    let frames = render_frames(request, animation, progress);
+    if progress.is_cancelled() {
+        return ExportResult::Cancelled;
+    }
+    let saved = match format {
+        LoopFormat::Gif => save_gif(&frames, path, animation),
+        LoopFormat::Apng => save_apng(&frames, path, animation),
+        LoopFormat::Y4m => save_y4m(&frames, path, request, animation),
+        LoopFormat::Frames => save_frames(&frames, path),
+    };
+    match saved {
+        Err(_) => ExportResult::ErrorUnknown,
+        Ok(_) => ExportResult::Done,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{data::*, fixtures};
+
+    fn request(filepath: &str, motion: LoopMotion) -> (ExportRequest, LoopAnimation) {
+        let animation = LoopAnimation {
+            motion,
+            frames: 4,
+            delay_ms: 50,
+            dither: true,
+        };
+        let request = ExportRequest {
+            fragment: FractalFragment {
+                width_px: 24,
+                height_px: 16,
+                top_left: Complex64::new(-2.0, 1.0),
+                bottom_right: Complex64::new(1.0, -1.0),
+            },
+            animation: Some(animation.clone()),
+            ..fixtures::export_request(filepath)
+        };
+        (request, animation)
+    }
+
+    #[test]
+    fn zooms_around_center() {
+        let (request, _) = request("./loop.gif", LoopMotion::Zoom { factor: 2.0 });
+        let zoomed = zoom(&request.fragment, 0.5);
+        assert_eq!(zoomed.top_left, Complex64::new(-1.25, 0.5));
+        assert_eq!(zoomed.bottom_right, Complex64::new(0.25, -0.5));
+    }
+
+    #[test]
+    fn saves_animated_gif() {
+        let path = ConstantPath::Circle {
+            center: Complex64::new(0.0, 0.0),
+            radius: 0.7885,
+        };
+        let motion = LoopMotion::JuliaMorph { path };
+        let (request, animation) = request("./loop.gif", motion);
+        let result = export_loop(&request, &animation, &Progress::default());
+        assert!(matches!(result, ExportResult::Done));
+
+        let file = File::open("./loop.gif").unwrap();
+        let mut decoder = gif::DecodeOptions::new().read_info(file).unwrap();
+        let mut frames = 0;
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            assert_eq!(frame.delay, 5);
+            frames += 1;
+        }
+        assert_eq!(frames, 4);
+    }
+
+    #[test]
+    fn saves_apng() {
+        let (request, animation) = request("./loop.apng", LoopMotion::PaletteCycle);
+        let result = export_loop(&request, &animation, &Progress::default());
+        assert!(matches!(result, ExportResult::Done));
+
+        let decoder = png::Decoder::new(File::open("./loop.apng").unwrap());
+        let reader = decoder.read_info().unwrap();
+        let control = reader.info().animation_control.unwrap();
+        assert_eq!(control.num_frames, 4);
+    }
+
+    #[test]
+    fn walks_polylines_at_steady_speed() {
+        let points = [(0.0, 0.0), (1.0, 0.0), (1.0, 3.0)];
+        let path = ConstantPath::Polyline {
+            points: points
+                .map(|(re, im)| ComplexPoint(Complex64::new(re, im)))
+                .to_vec(),
+        };
+        assert!(!path.is_closed());
+        assert_eq!(path.at(0.0), Complex64::new(0.0, 0.0));
+        assert_eq!(path.at(0.5), Complex64::new(1.0, 1.0));
+        assert_eq!(path.at(1.0), Complex64::new(1.0, 3.0));
+    }
+
+    #[test]
+    fn traces_main_cardioid() {
+        let cusp = ConstantPath::Cardioid.at(0.0);
+        let tip = ConstantPath::Cardioid.at(0.5);
+        assert!((cusp - Complex64::new(0.25, 0.0)).norm() < 1e-12);
+        assert!((tip - Complex64::new(-0.75, 0.0)).norm() < 1e-12);
+    }
+
+    #[test]
+    fn cycles_palette_into_frame_sequence() {
+        let (request, animation) = request("./cycle", LoopMotion::PaletteCycle);
+        let result = export_loop(&request, &animation, &Progress::default());
+        assert!(matches!(result, ExportResult::Done));
+
+        let first = image::open("./cycle/frame_00000.png").unwrap();
+        let second = image::open("./cycle/frame_00001.png").unwrap();
+        assert_ne!(first.as_bytes(), second.as_bytes());
+    }
+}
@@ -1,35 +1,184 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod animation;
+mod boundary;
+mod cache;
+mod checkpoint;
 mod color;
 mod convert;
 mod data;
+mod export;
+mod field;
+#[cfg(test)]
+mod fixtures;
 mod fractal;
+mod hdr;
+mod jobs;
+mod loops;
+mod metadata;
+mod pool;
+mod progress;
+mod progressive;
 mod renderer;
+mod scene;
+mod streaming;
+mod subdivision;
+mod symmetry;
+mod tiles;
+mod video;
 
-use data::{ExportRequest, ExportResult, TileRequest};
-use renderer::{into_data_url, FractalImage};
+use cache::FieldCache;
+use data::{
+    AnimationRequest, ExportRequest, ExportResult, RenderParams, TileBatch, TileId, TileRequest,
+};
+use jobs::{ExportQueue, JobId, JobInfo, JobStatus, JobTask};
+use scene::{Scene, SceneError};
+use std::error::Error;
+use tauri::{
+    http::{Request, Response, ResponseBuilder},
+    AppHandle, Manager, State,
+};
+use tiles::{QueuedTile, RenderedTile, RunningTiles, TilePriority, TileQueue, TileStore};
 
+/// Returns false, when the tile was cancelled. Rendered tiles (and
+/// passes of progressive ones) are fetched through the tile:// protocol
 #[tauri::command]
-async fn calc_tile(request: TileRequest) -> String {
-    let fractal: FractalImage = request.into();
-    let image = fractal.render_for_ui();
-    into_data_url(image)
+async fn calc_tile(request: TileRequest, app: AppHandle) -> Result<bool, ()> {
+    Ok(render_tile(&app, request.into()))
 }
 
-#[tauri::command]
-async fn export_image(request: ExportRequest) -> ExportResult {
-    let path = request.filepath.clone();
-    if image::ImageFormat::from_path(&path).is_err() {
-        return ExportResult::ErrorBadFileType;
+/// Keeps the rendered passes in the store and announces them
+fn render_tile(app: &AppHandle, tile: QueuedTile) -> bool {
+    let (id, session, generation) = (tile.id, tile.session, tile.generation);
+    let store = app.state::<TileStore>();
+    let announce = |step, ready| {
+        let tile = RenderedTile {
+            id,
+            session,
+            generation,
+            step,
+            ready,
+        };
+        let _ = app.emit_all("tile_rendered", tile);
+    };
+    let cache = app.state::<FieldCache>();
+    let running = app.state::<RunningTiles>();
+    let pixels = tiles::render_tile(tile, &cache, &running, |step, pixels| {
+        store.insert((session, id, step), generation, pixels);
+        announce(step, true);
+    });
+    let ready = pixels.is_some();
+    if let Some(pixels) = pixels {
+        store.insert((session, id, 1), generation, pixels);
     }
-    let fractal: FractalImage = request.into();
-    match fractal.render_on_threads().save(path) {
-        Err(_) => ExportResult::ErrorUnknown,
-        Ok(_) => ExportResult::Done,
+    announce(1, ready);
+    ready
+}
+
+fn serve_tile(app: &AppHandle, request: &Request) -> Result<Response, Box<dyn Error>> {
+    let store = app.state::<TileStore>();
+    let response = ResponseBuilder::new().header("Access-Control-Allow-Origin", "*");
+    match tiles::parse_tile_uri(request.uri()).and_then(|key| store.take(key)) {
+        None => response.status(404).body(Vec::new()),
+        Some(pixels) => response.mimetype("application/octet-stream").body(pixels),
     }
 }
 
+/// Rendered tiles are announced by "tile_rendered" events, as they finish
+#[tauri::command]
+fn calc_tiles(batch: TileBatch, queue: State<TileQueue>) {
+    queue.schedule(batch.into_queued())
+}
+
+#[tauri::command]
+fn prioritize_tiles(priorities: Vec<TilePriority>, queue: State<TileQueue>) {
+    queue.prioritize(&priorities)
+}
+
+/// Called by the frontend when it loads. Drops tiles of the previous
+/// page, and starts counting generations from zero again
+#[tauri::command]
+fn start_tile_session(
+    queue: State<TileQueue>,
+    tiles: State<RunningTiles>,
+    store: State<TileStore>,
+) -> u32 {
+    queue.clear();
+    let session = tiles.start_session();
+    store.cancel(session, &[], 0);
+    session
+}
+
+#[tauri::command]
+fn cancel_tiles(
+    ids: Vec<TileId>,
+    generation: u32,
+    queue: State<TileQueue>,
+    tiles: State<RunningTiles>,
+    store: State<TileStore>,
+) -> usize {
+    store.cancel(tiles.session(), &ids, generation);
+    queue.cancel(&ids, generation) + tiles.cancel(&ids, generation)
+}
+
+#[tauri::command]
+fn enqueue_export(request: ExportRequest, queue: State<ExportQueue>) -> JobId {
+    queue.enqueue(JobTask::Export(Box::new(request)))
+}
+
+#[tauri::command]
+fn resume_export(manifest: String, queue: State<ExportQueue>) -> JobId {
+    queue.enqueue(JobTask::Resume { manifest })
+}
+
+#[tauri::command]
+fn list_exports(queue: State<ExportQueue>) -> Vec<JobInfo> {
+    queue.list()
+}
+
+#[tauri::command]
+fn export_status(id: JobId, queue: State<ExportQueue>) -> Option<JobStatus> {
+    queue.status(id)
+}
+
+#[tauri::command]
+fn cancel_export(id: JobId, queue: State<ExportQueue>) -> bool {
+    queue.cancel(id)
+}
+
+#[tauri::command]
+fn set_export_concurrency(concurrency: usize, queue: State<ExportQueue>) {
+    queue.set_concurrency(concurrency)
+}
+
+/// Returns the number of threads actually started
+#[tauri::command]
+fn set_render_threads(threads: usize) -> usize {
+    pool::set_threads(threads);
+    pool::threads()
+}
+
+#[tauri::command]
+async fn export_animation(request: AnimationRequest) -> ExportResult {
+    animation::export_animation(request)
+}
+
+#[tauri::command]
+async fn read_render_params(filepath: String) -> Option<RenderParams> {
+    metadata::read_params(filepath)
+}
+
+#[tauri::command]
+async fn save_scene(scene: Scene, filepath: String) -> Result<(), SceneError> {
+    scene.save(filepath)
+}
+
+#[tauri::command]
+async fn load_scene(filepath: String) -> Result<Scene, SceneError> {
+    Scene::load(filepath)
+}
+
 #[tauri::command]
 fn get_default_save_dir() -> Option<String> {
     let path = match dirs::picture_dir() {
@@ -44,9 +193,43 @@ fn get_default_save_dir() -> Option<String> {
 
 fn main() {
     tauri::Builder::default()
+        .manage(FieldCache::default())
+        .manage(RunningTiles::default())
+        .manage(TileStore::default())
+        .register_uri_scheme_protocol(tiles::PROTOCOL, serve_tile)
+        .setup(|app| {
+            // Spare one thread for UI (app stutters otherwise)
+            pool::set_threads(pool::default_threads() - 1);
+            let handle = app.handle();
+            let queue = ExportQueue::new(1, move |job| {
+                let _ = handle.emit_all("export_job", job);
+            });
+            app.manage(queue);
+
+            let handle = app.handle();
+            let tiles = TileQueue::new(tiles::TILE_WORKERS, move |tile| {
+                render_tile(&handle, tile);
+            });
+            app.manage(tiles);
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             calc_tile,
-            export_image,
+            calc_tiles,
+            prioritize_tiles,
+            start_tile_session,
+            cancel_tiles,
+            enqueue_export,
+            resume_export,
+            list_exports,
+            export_status,
+            cancel_export,
+            set_export_concurrency,
+            set_render_threads,
+            export_animation,
+            read_render_params,
+            save_scene,
+            load_scene,
             get_default_save_dir
         ])
         .run(tauri::generate_context!())
@@ -0,0 +1,143 @@
+//! Render parameters stored alongside exported images, so that an
+//! exported picture can be opened in the viewer again. PNGs carry them
+//! in an iTXt chunk, other formats get a `<filename>.json` sidecar file.
+
+use crate::{data::RenderParams, renderer::ImageBuffer};
+use image::{codecs::jpeg::JpegEncoder, ImageFormat};
+use std::{
+    error::Error,
+    ffi::OsString,
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+pub const PARAMS_KEYWORD: &str = "FractalParams";
+
+pub fn sidecar_path(path: &Path) -> PathBuf {
+    let mut sidecar = OsString::from(path);
+    sidecar.push(".json");
+    sidecar.into()
+}
+
+fn is_png(path: &Path) -> bool {
+    matches!(ImageFormat::from_path(path), Ok(ImageFormat::Png))
+}
+
+fn save_png(image: &ImageBuffer, path: &Path, params: String) -> Result<(), Box<dyn Error>> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, image.width(), image.height());
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.add_itxt_chunk(PARAMS_KEYWORD.to_owned(), params)?;
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(image.as_raw())?;
+    writer.finish()?;
+    Ok(())
+}
+
+pub fn save_with_params(
+    image: &ImageBuffer,
+    path: impl AsRef<Path>,
+    params: &RenderParams,
+    jpeg_quality: u8,
+) -> Result<(), Box<dyn Error>> {
+    let path = path.as_ref();
+    let json = serde_json::to_string(params)?;
+    if is_png(path) {
+        return save_png(image, path, json);
+    }
+
+    if let Ok(ImageFormat::Jpeg) = ImageFormat::from_path(path) {
+        let file = BufWriter::new(File::create(path)?);
+        JpegEncoder::new_with_quality(file, jpeg_quality).encode_image(image)?;
+    } else {
+        image.save(path)?;
+    }
+    fs::write(sidecar_path(path), json)?;
+    Ok(())
+}
+
+fn read_png_params(path: &Path) -> Option<String> {
+    let decoder = png::Decoder::new(File::open(path).ok()?);
+    let reader = decoder.read_info().ok()?;
+    let info = reader.info();
+    let utf8 = info
+        .utf8_text
+        .iter()
+        .find(|chunk| chunk.keyword == PARAMS_KEYWORD)
+        .and_then(|chunk| chunk.get_text().ok());
+    let latin1 = info
+        .uncompressed_latin1_text
+        .iter()
+        .find(|chunk| chunk.keyword == PARAMS_KEYWORD)
+        .map(|chunk| chunk.text.clone());
+    utf8.or(latin1)
+}
+
+pub fn read_params(path: impl AsRef<Path>) -> Option<RenderParams> {
+    let path = path.as_ref();
+    let json = match is_png(path) {
+        true => read_png_params(path)?,
+        false => fs::read_to_string(sidecar_path(path)).ok()?,
+    };
+    serde_json::from_str(&json).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::*;
+    use crate::fractal::CYCLE_TOLERANCE;
+    use num::complex::Complex64;
+
+    fn params() -> RenderParams {
+        RenderParams {
+            fractal: FractalConfig {
+                variant: FractalVariant::JuliaSet {
+                    constant: Complex64::new(0.34, 0.08),
+                },
+                max_iterations: 512,
+                cycle_tolerance: CYCLE_TOLERANCE,
+            },
+            fragment: FractalFragment {
+                width_px: 8,
+                height_px: 4,
+                top_left: Complex64::new(-2.0, 1.0),
+                bottom_right: Complex64::new(2.0, -1.0),
+            },
+            color: ColorConfig {
+                color: ColorHex {
+                    hex_start: "#ff0000".to_owned(),
+                    hex_end: "#ffff00".to_owned(),
+                },
+                brightness: 2.0,
+                anti_alias: true,
+                method: ColorMethod::Exponential { power: 0.8 },
+            },
+        }
+    }
+
+    #[test]
+    fn finds_sidecar_next_to_image() {
+        let sidecar = sidecar_path(Path::new("exports/fractal.jpeg"));
+        assert_eq!(sidecar, Path::new("exports/fractal.jpeg.json"));
+    }
+
+    #[test]
+    fn reads_params_back_from_png() {
+        let image = ImageBuffer::new(8, 4);
+        save_with_params(&image, "./params.png", &params(), 75).unwrap();
+        let read = read_params("./params.png").unwrap();
+        assert_eq!(read.fractal.max_iterations, 512);
+        assert_eq!(read.fragment.top_left, Complex64::new(-2.0, 1.0));
+    }
+
+    #[test]
+    fn reads_params_back_from_sidecar() {
+        let image = ImageBuffer::new(8, 4);
+        save_with_params(&image, "./params.jpeg", &params(), 75).unwrap();
+        let read = read_params("./params.jpeg").unwrap();
+        assert_eq!(read.color.brightness, 2.0);
+    }
+}
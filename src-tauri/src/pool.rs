@@ -0,0 +1,54 @@
+//! Long lived workers shared by all renders. Renders are split into
+//! small blocks of rows and idle workers steal blocks from busy ones,
+//! so expensive areas of a fractal don't leave the other cores idle.
+
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::{
+    num::NonZeroUsize,
+    sync::{Arc, OnceLock, RwLock},
+    thread,
+};
+
+static POOL: OnceLock<RwLock<Arc<ThreadPool>>> = OnceLock::new();
+
+pub fn default_threads() -> usize {
+    thread::available_parallelism()
+        .unwrap_or(NonZeroUsize::MIN)
+        .get()
+}
+
+fn build(threads: usize) -> Arc<ThreadPool> {
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(threads.max(1))
+        .thread_name(|id| format!("render-{id}"))
+        .build()
+        .expect("failed to start render threads");
+    Arc::new(pool)
+}
+
+fn pool() -> &'static RwLock<Arc<ThreadPool>> {
+    POOL.get_or_init(|| RwLock::new(build(default_threads())))
+}
+
+/// Replaces the pool, renders already running finish on the old one
+pub fn set_threads(threads: usize) {
+    *pool().write().unwrap() = build(threads);
+}
+
+pub fn threads() -> usize {
+    current().current_num_threads()
+}
+
+pub fn current() -> Arc<ThreadPool> {
+    pool().read().unwrap().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn builds_pool_of_requested_size() {
+        use super::*;
+        assert_eq!(build(3).current_num_threads(), 3);
+        assert_eq!(build(0).current_num_threads(), 1);
+    }
+}
@@ -0,0 +1,92 @@
+//! Progress of long renders, counted in rows and shared by all the
+//! threads working on them. Also carries the flag, which cancels them.
+
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Arc,
+};
+
+#[derive(Clone)]
+pub struct Progress {
+    rows_expected: Arc<AtomicU64>,
+    rows_done: Arc<AtomicU64>,
+    cancelled: Arc<AtomicBool>,
+    /// Called with the finished fraction (0.0 - 1.0), at every percent
+    report: Arc<dyn Fn(f64) + Send + Sync>,
+}
+
+impl Progress {
+    pub fn new(report: impl Fn(f64) + Send + Sync + 'static) -> Self {
+        Self {
+            rows_expected: Arc::new(AtomicU64::new(1)),
+            rows_done: Arc::new(AtomicU64::new(0)),
+            cancelled: Arc::new(AtomicBool::new(false)),
+            report: Arc::new(report),
+        }
+    }
+
+    /// Starts counting again, towards the given number of rows
+    pub fn expect_rows(&self, rows: u64) {
+        self.rows_expected.store(rows, Ordering::Relaxed);
+        self.rows_done.store(0, Ordering::Relaxed);
+        (self.report)(0.0);
+    }
+
+    pub fn row_done(&self) {
+        let expected = self.rows_expected.load(Ordering::Relaxed).max(1);
+        let done = self.rows_done.fetch_add(1, Ordering::Relaxed) + 1;
+        let percent = |rows: u64| rows * 100 / expected;
+        if percent(done) != percent(done - 1) {
+            (self.report)((done as f64 / expected as f64).min(1.0));
+        }
+    }
+
+    pub fn fraction(&self) -> f64 {
+        let expected = self.rows_expected.load(Ordering::Relaxed).max(1);
+        let done = self.rows_done.load(Ordering::Relaxed);
+        (done as f64 / expected as f64).min(1.0)
+    }
+
+    /// All the expected rows were rendered. Renders stopped by a cancel
+    /// never get there, renders cancelled after their last row do
+    pub fn is_complete(&self) -> bool {
+        let expected = self.rows_expected.load(Ordering::Relaxed);
+        self.rows_done.load(Ordering::Relaxed) >= expected
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Progress nobody is listening to
+impl Default for Progress {
+    fn default() -> Self {
+        Self::new(|_| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn reports_every_percent() {
+        use super::*;
+        use std::sync::Mutex;
+
+        let reports = Arc::new(Mutex::new(vec![]));
+        let reported = reports.clone();
+        let progress = Progress::new(move |fraction| reported.lock().unwrap().push(fraction));
+        progress.expect_rows(1000);
+        for _ in 0..1000 {
+            progress.row_done();
+        }
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 101);
+        assert_eq!(reports.last(), Some(&1.0));
+        assert_eq!(progress.fraction(), 1.0);
+    }
+}
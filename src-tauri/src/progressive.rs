@@ -0,0 +1,126 @@
+//! Progressive renders of tiles. The first pass samples every 8th pixel
+//! of every 8th row, each next pass halves the spacing and evaluates only
+//! the samples missing so far. Pixels without a sample of their own take
+//! the one at the corner of their block, so every pass is a whole picture.
+//! Filled images finish with a filled render instead of the last pass,
+//! which skips the inside of the set and reuses the coarse samples.
+
+use crate::{
+    field::IterationField,
+    fractal::ComplexItem,
+    pool,
+    progress::Progress,
+    renderer::{Filling, FractalImage},
+};
+use rayon::prelude::*;
+
+/// Spacing of the samples in the consecutive passes
+pub const STEPS: [u32; 4] = [8, 4, 2, 1];
+
+/// Calls `on_pass` with the spacing and the field of every pass but the
+/// last one. Returns the full field, or nothing when cancelled
+pub fn render(
+    image: &FractalImage,
+    progress: &Progress,
+    mut on_pass: impl FnMut(u32, IterationField),
+) -> Option<IterationField> {
+    let fragment = image.fragment().clone();
+    let (width, height) = (fragment.width_px, fragment.height_px);
+    let mut samples = vec![image.unevaluated(); width as usize * height as usize];
+    let mut coarser = None;
+
+    for step in STEPS {
+        if step == 1 && image.filling() != Filling::Off {
+            let image = image.clone().with_samples(samples, coarser.unwrap_or(1));
+            let field = image.render_field_parallel();
+            return Some(field).filter(|_| !progress.is_cancelled());
+        }
+        let is_sampled = |x: u32, row: u32| coarser.is_some_and(|c| x % c == 0 && row % c == 0);
+        let rows: Vec<u32> = (0..height).step_by(step as usize).collect();
+        let evaluated: Vec<(u32, Vec<(u32, ComplexItem)>)> = pool::current().install(|| {
+            rows.into_par_iter()
+                .map(|row| {
+                    if progress.is_cancelled() {
+                        return (row, vec![]);
+                    }
+                    let items = (0..width)
+                        .step_by(step as usize)
+                        .filter(|&x| !is_sampled(x, row))
+                        .map(|x| (x, image.eval_at(x, row)))
+                        .collect();
+                    (row, items)
+                })
+                .collect()
+        });
+        if progress.is_cancelled() {
+            return None;
+        }
+        for (row, items) in evaluated {
+            for (x, item) in items {
+                samples[(row * width + x) as usize] = item;
+            }
+        }
+        if step == 1 {
+            return Some(IterationField::new(fragment, samples));
+        }
+
+        let corner =
+            |row: u32, x: u32| samples[((row - row % step) * width + x - x % step) as usize];
+        let filled = (0..height)
+            .flat_map(|row| (0..width).map(move |x| corner(row, x)))
+            .collect();
+        on_pass(step, IterationField::new(fragment.clone(), filled));
+        coarser = Some(step);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fixtures, renderer::Strategy};
+    use num::complex::Complex64;
+
+    fn image(width_px: u32, height_px: u32) -> FractalImage {
+        let (top_left, bottom_right) = (Complex64::new(-2.0, 1.0), Complex64::new(1.0, -0.8));
+        fixtures::image(width_px, height_px, top_left, bottom_right)
+    }
+
+    #[test]
+    fn refines_into_full_render() {
+        let image = image(20, 12);
+        let mut passes = vec![];
+        let field = render(&image, &Progress::default(), |step, field| {
+            assert_eq!(field.items.len(), 20 * 12);
+            passes.push(step);
+        });
+        assert_eq!(passes, [8, 4, 2]);
+
+        let indices = |field: IterationField| -> Vec<_> {
+            field.items.iter().map(|item| item.index).collect()
+        };
+        assert_eq!(indices(field.unwrap()), indices(image.render_field()));
+    }
+
+    #[test]
+    fn finishes_filled_images_with_filled_render() {
+        let image = image(40, 30)
+            .with_filling(Filling::Uniform)
+            .with_strategy(Strategy::Subdivision);
+        let mut passes = vec![];
+        let field = render(&image, &Progress::default(), |step, _| passes.push(step));
+        assert_eq!(passes, [8, 4, 2]);
+        let indices = |field: IterationField| -> Vec<_> {
+            field.items.iter().map(|item| item.index).collect()
+        };
+        let full = image.with_filling(Filling::Off).render_field();
+        assert_eq!(indices(field.unwrap()), indices(full));
+    }
+
+    #[test]
+    fn stops_when_cancelled() {
+        let progress = Progress::default();
+        progress.cancel();
+        assert!(render(&image(8, 8), &progress, |_, _| panic!()).is_none());
+    }
+}
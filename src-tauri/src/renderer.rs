@@ -1,19 +1,105 @@
 use crate::{
+    boundary,
     color::ColorCreator,
     data::{FractalFragment, Rgb},
-    fractal::Fractal,
+    field::IterationField,
+    fractal::{ComplexItem, Fractal},
+    pool,
+    progress::Progress,
+    subdivision,
+    symmetry::{Mirror, Mirrored},
 };
 use image::ImageBuffer as __ImageBuffer;
 use num::complex::Complex64;
-use std::{mem::size_of, num::NonZeroUsize, thread, vec};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{mem::size_of, sync::Arc};
 
 pub type ImageBuffer = __ImageBuffer<Rgb, Vec<u8>>;
 
+/// Which uniform areas are filled without evaluating them,
+/// from the least to the most filling. Filling guesses the inside
+/// of an area from its outline, and misses thin filaments and
+/// islands, which slip between the evaluated pixels
+#[derive(Serialize, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Filling {
+    /// Every pixel is evaluated
+    #[default]
+    Off,
+    /// Only areas inside the set. Needs colorings, which
+    /// color the whole set the same (see ColorCreator::filling)
+    Interior,
+    /// All areas of one iteration count. Needs colorings,
+    /// which don't look at anything but the iteration count
+    Uniform,
+}
+
+impl Filling {
+    pub fn fills(self, item: &ComplexItem) -> bool {
+        match self {
+            Self::Off => false,
+            Self::Interior => item.index == item.max_index,
+            Self::Uniform => true,
+        }
+    }
+}
+
+/// How the uniform areas are found, if at all
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Debug)]
+pub enum Strategy {
+    /// Every pixel is evaluated, whatever the filling
+    #[default]
+    Off,
+    /// Mariani-Silver, see subdivision.rs
+    Subdivision,
+    /// Follows the contours of the areas, see boundary.rs. Best for
+    /// views with high max_iterations, filled with the inside of the set
+    BoundaryTracing,
+}
+
+/// Rows rendered as a single piece of work. Small blocks
+/// let idle workers take over the rest of an expensive area
+const BLOCK_ROWS: u32 = 4;
+/// Filled renders need taller blocks to find large uniform areas
+const FILLED_BLOCK_ROWS: u32 = 64;
+/// Filled blocks hold a whole field, so wide ones get fewer rows
+const FILLED_BLOCK_PIXELS: u32 = 1 << 18;
+
+#[derive(Clone, Copy)]
+struct Rotation {
+    pivot: Complex64,
+    turn: Complex64,
+}
+
+/// Items evaluated before, on every `spacing`-th pixel of every
+/// `spacing`-th row, starting from the bottom left corner
+#[derive(Clone)]
+struct Samples {
+    items: Arc<Vec<ComplexItem>>,
+    width: u32,
+    spacing: u32,
+    /// Row of the samples, at which the image starts
+    first_row: u32,
+}
+
+impl Samples {
+    fn get(&self, x: u32, row: u32) -> Option<ComplexItem> {
+        let row = self.first_row + row;
+        let sampled = x % self.spacing == 0 && row % self.spacing == 0;
+        Some(self.items[(row * self.width + x) as usize]).filter(|_| sampled)
+    }
+}
+
 #[derive(Clone)]
 pub struct FractalImage {
     fractal: Fractal,
     fragment: FractalFragment,
     color: ColorCreator,
+    rotation: Option<Rotation>,
+    progress: Option<Progress>,
+    filling: Filling,
+    strategy: Strategy,
+    samples: Option<Samples>,
 }
 
 impl FractalImage {
@@ -22,6 +108,69 @@ impl FractalImage {
             fractal,
             fragment,
             color,
+            rotation: None,
+            progress: None,
+            filling: Filling::Off,
+            strategy: Strategy::default(),
+            samples: None,
+        }
+    }
+
+    /// Makes the render count its rows, and stop when cancelled
+    pub fn with_progress(self, progress: Progress) -> Self {
+        Self {
+            progress: Some(progress),
+            ..self
+        }
+    }
+
+    /// Fills uniform areas without evaluating them. Fractals, whose areas
+    /// can enclose other areas, are filled less, see Fractal::filling
+    pub fn with_filling(self, filling: Filling) -> Self {
+        Self {
+            filling: filling.min(self.fractal.filling()),
+            ..self
+        }
+    }
+
+    pub fn with_strategy(self, strategy: Strategy) -> Self {
+        Self { strategy, ..self }
+    }
+
+    /// Reuses the items of the image, which were already evaluated on every
+    /// `spacing`-th pixel of every `spacing`-th row, instead of evaluating
+    /// them again. Only filled renders go through those single pixels
+    pub fn with_samples(self, items: Vec<ComplexItem>, spacing: u32) -> Self {
+        let samples = Samples {
+            items: Arc::new(items),
+            width: self.fragment.width_px,
+            spacing: spacing.max(1),
+            first_row: 0,
+        };
+        Self {
+            samples: Some(samples),
+            ..self
+        }
+    }
+
+    /// Filling of the render, which is off without a strategy
+    pub fn filling(&self) -> Filling {
+        match self.strategy {
+            Strategy::Off => Filling::Off,
+            _ => self.filling,
+        }
+    }
+
+    /// Rotates the fragment around its center (counterclockwise, in radians)
+    pub fn with_rotation(self, angle: f64) -> Self {
+        let center = (self.fragment.top_left + self.fragment.bottom_right) / 2.0;
+        let rotation = Rotation {
+            pivot: center,
+            turn: Complex64::from_polar(1.0, angle),
+        };
+        Self {
+            rotation: Some(rotation).filter(|_| angle != 0.0),
+            ..self
         }
     }
 
@@ -32,104 +181,215 @@ impl FractalImage {
         (real_max - real_min) / width
     }
 
-    pub fn render(&self) -> ImageBuffer {
+    /// Makes the rendered fields carry distance estimates. Every pixel
+    /// is evaluated, filled ones would copy the distances of others
+    pub fn with_distance(self) -> Self {
+        Self {
+            fractal: self.fractal.with_distance(),
+            filling: Filling::Off,
+            ..self
+        }
+    }
+
+    pub fn fragment(&self) -> &FractalFragment {
+        &self.fragment
+    }
+
+    pub fn unevaluated(&self) -> ComplexItem {
+        self.fractal.unevaluated()
+    }
+
+    /// Evaluates a single pixel, rows are counted from the bottom
+    pub fn eval_at(&self, x: u32, row: u32) -> ComplexItem {
+        if let Some(item) = self
+            .samples
+            .as_ref()
+            .and_then(|samples| samples.get(x, row))
+        {
+            return item;
+        }
         let step = self.pixel_size();
         let size = &self.fragment;
-        let mut image = ImageBuffer::new(size.width_px, size.height_px);
-        let mut real = size.top_left.re;
-        for x in 0..size.width_px {
-            let mut imag = size.bottom_right.im;
-            for y in 0..size.height_px {
+        let point = Complex64::new(
+            size.top_left.re + x as f64 * step,
+            size.bottom_right.im + row as f64 * step,
+        );
+        self.fractal.eval(match self.rotation {
+            None => point,
+            Some(Rotation { pivot, turn }) => pivot + (point - pivot) * turn,
+        })
+    }
+
+    /// Whether the point lies within the pixels between the corners,
+    /// both of them included. Rows are counted from the bottom
+    pub fn encloses(&self, point: Complex64, (x0, y0): (u32, u32), (x1, y1): (u32, u32)) -> bool {
+        let point = match self.rotation {
+            None => point,
+            Some(Rotation { pivot, turn }) => pivot + (point - pivot) / turn,
+        };
+        let step = self.pixel_size();
+        let x = (point.re - self.fragment.top_left.re) / step;
+        let row = (point.im - self.fragment.bottom_right.im) / step;
+        (x0 as f64..=x1 as f64).contains(&x) && (y0 as f64..=y1 as f64).contains(&row)
+    }
+
+    /// Visits points of the fragment row by row, starting from
+    /// the bottom left corner. Cancelled renders stop between rows
+    fn for_each_point(&self, mut visit: impl FnMut(Complex64)) {
+        let step = self.pixel_size();
+        let size = &self.fragment;
+        let mut imag = size.bottom_right.im;
+        for _ in 0..size.height_px {
+            if self.progress.as_ref().is_some_and(Progress::is_cancelled) {
+                return;
+            }
+            let mut real = size.top_left.re;
+            for _ in 0..size.width_px {
                 let point = Complex64::new(real, imag);
-                let divergence = self.fractal.eval(point);
-                let pixel = self.color.get_pixel(&divergence);
-                image.put_pixel(x, y, pixel);
-                imag += step;
+                visit(match self.rotation {
+                    None => point,
+                    Some(Rotation { pivot, turn }) => pivot + (point - pivot) * turn,
+                });
+                real += step;
+            }
+            imag += step;
+            if let Some(progress) = &self.progress {
+                progress.row_done();
             }
-            real += step;
         }
-        image
     }
 
-    fn split_work(&self, chunks: u32) -> Vec<Self> {
-        if chunks == 1 {
-            return vec![FractalImage { ..self.clone() }];
+    pub fn render(&self) -> ImageBuffer {
+        if self.filling() != Filling::Off {
+            return self.render_field().colorize(&self.color);
         }
+        let size = &self.fragment;
+        let pixel_count = (size.width_px * size.height_px) as usize;
+        let mut pixels = Vec::with_capacity(pixel_count * size_of::<Rgb>());
+        self.for_each_point(|point| {
+            let divergence = self.fractal.eval(point);
+            pixels.extend(self.color.get_pixel(&divergence).0);
+        });
+        pixels.resize(pixel_count * size_of::<Rgb>(), 0);
+        ImageBuffer::from_raw(size.width_px, size.height_px, pixels).unwrap()
+    }
 
-        let step = self.pixel_size();
-        let size = self.fragment.clone();
-        let mut jobs = vec![];
-
-        let chunk_height = size.height_px / chunks;
-        let chunk_bottom_im = |chunk_id: u32| {
-            let id = chunk_id as f64;
-            let height = chunk_height as f64;
-            id * height * step + size.bottom_right.im
+    pub fn render_field(&self) -> IterationField {
+        let size = &self.fragment;
+        let pixel_count = (size.width_px * size.height_px) as usize;
+        let progress = self.progress.as_ref();
+        let filled = match (self.strategy, self.filling) {
+            (Strategy::Off, _) | (_, Filling::Off) => None,
+            (Strategy::Subdivision, filling) => Some(subdivision::render(self, filling, progress)),
+            (Strategy::BoundaryTracing, filling) => Some(boundary::render(self, filling, progress)),
         };
-
-        for id in 0..=(chunks - 2) {
-            jobs.push(FractalImage {
-                fragment: FractalFragment {
-                    width_px: size.width_px,
-                    height_px: chunk_height,
-                    top_left: Complex64::new(size.top_left.re, chunk_bottom_im(id + 1)),
-                    bottom_right: Complex64::new(size.bottom_right.re, chunk_bottom_im(id)),
-                },
-                ..self.clone()
-            });
+        if let Some(items) = filled {
+            return IterationField::new(size.clone(), items);
         }
+        let mut items = Vec::with_capacity(pixel_count);
+        self.for_each_point(|point| items.push(self.fractal.eval(point)));
+        items.resize(pixel_count, self.fractal.unevaluated());
+        IterationField::new(size.clone(), items)
+    }
 
-        jobs.push(FractalImage {
+    /// Part of the image with only the given rows
+    fn rows(&self, first_row: u32, height_px: u32) -> Self {
+        let step = self.pixel_size();
+        let size = &self.fragment;
+        let bottom_im = size.bottom_right.im + first_row as f64 * step;
+        let top_im = bottom_im + height_px as f64 * step;
+        let samples = self.samples.clone().map(|samples| Samples {
+            first_row: samples.first_row + first_row,
+            ..samples
+        });
+        FractalImage {
             fragment: FractalFragment {
-                height_px: chunk_height + size.height_px % chunks,
-                bottom_right: Complex64::new(size.bottom_right.re, chunk_bottom_im(chunks - 1)),
-                ..size
+                width_px: size.width_px,
+                height_px,
+                top_left: Complex64::new(size.top_left.re, top_im),
+                bottom_right: Complex64::new(size.bottom_right.re, bottom_im),
             },
+            samples,
             ..self.clone()
-        });
-
-        let computed_height_px: u32 = jobs.iter().map(|j| j.fragment.height_px).sum();
-        if computed_height_px != size.height_px {
-            panic!("Slow down, cowboy");
         }
-        jobs
     }
 
-    fn delegate_and_run(&self, chunks: u32) -> ImageBuffer {
-        let jobs = self.split_work(chunks);
-        let mut handles = vec![];
-        let mut pixels = vec![];
+    /// Splits the image into strips of rows (the last one may be
+    /// shorter), starting from the bottom, like the rendered rows
+    pub fn into_strips(self, rows: u32) -> impl Iterator<Item = Self> {
+        let height = self.fragment.height_px;
+        let rows = rows.max(1);
+        (0..height)
+            .step_by(rows as usize)
+            .map(move |first_row| self.rows(first_row, rows.min(height - first_row)))
+    }
 
-        for job in jobs {
-            handles.push(thread::spawn(move || job.render().into_raw()))
+    /// Symmetric fractals are mirrored, when the fragment straddles
+    /// their axis. Rotated fragments are never aligned with it
+    fn mirror(&self) -> Option<Mirror> {
+        if self.rotation.is_some() {
+            return None;
         }
+        Mirror::find(&self.fragment, self.pixel_size(), self.fractal.symmetry()?)
+    }
 
-        for handle in handles {
-            pixels.append(&mut handle.join().unwrap())
+    /// Renders blocks of rows on the render pool, in the order of the rows
+    fn render_blocks<T: Send>(&self, work: impl Fn(FractalImage) -> Vec<T> + Sync) -> Vec<T> {
+        let rows = match self.filling() {
+            Filling::Off => BLOCK_ROWS,
+            _ => (FILLED_BLOCK_PIXELS / self.fragment.width_px.max(1))
+                .clamp(BLOCK_ROWS, FILLED_BLOCK_ROWS),
+        };
+        let blocks: Vec<_> = self.clone().into_strips(rows).collect();
+        let rendered: Vec<Vec<T>> =
+            pool::current().install(|| blocks.into_par_iter().map(&work).collect());
+        let mut items = Vec::with_capacity(rendered.iter().map(Vec::len).sum());
+        rendered.into_iter().for_each(|block| items.extend(block));
+        items
+    }
+
+    /// Renders the evaluated rows of the mirror in blocks, like
+    /// `render_blocks`, and mirrors them onto the rest of the rows
+    fn render_mirrored<T: Mirrored + Send>(
+        &self,
+        mirror: Mirror,
+        work: impl Fn(FractalImage) -> Vec<T> + Sync,
+        convert: impl Fn(ComplexItem) -> T,
+    ) -> Vec<T> {
+        let half = self.rows(mirror.rows.start, mirror.rows.len() as u32);
+        let evaluated = half.render_blocks(work);
+        let eval = |x, row| convert(self.eval_at(x, row));
+        let pixels = mirror.unfold(&self.fragment, evaluated, eval);
+        if let Some(progress) = &self.progress {
+            (0..mirror.mirrored_rows(&self.fragment)).for_each(|_| progress.row_done());
         }
+        pixels
+    }
 
+    pub fn render_parallel(self) -> ImageBuffer {
+        let pixels = match self.mirror() {
+            Some(mirror) => {
+                let work = |block: FractalImage| block.render().pixels().copied().collect();
+                let color = |item| self.color.get_pixel(&item);
+                let pixels = self.render_mirrored(mirror, work, color);
+                let mut raw = Vec::with_capacity(pixels.len() * size_of::<Rgb>());
+                raw.extend(pixels.iter().flat_map(|pixel| pixel.0));
+                raw
+            }
+            None => self.render_blocks(|block| block.render().into_raw()),
+        };
         let size = &self.fragment;
         ImageBuffer::from_raw(size.width_px, size.height_px, pixels).unwrap()
     }
 
-    pub fn render_on_threads(self) -> ImageBuffer {
-        let chunks = thread::available_parallelism()
-            .unwrap_or(NonZeroUsize::MIN)
-            .get()
-            * 4;
-        self.delegate_and_run(chunks as u32)
-        // Some chunks are mutch faster to compute than others, if we
-        // make more threads than CPU cores, the load will average out
-    }
-
-    pub fn render_for_ui(self) -> ImageBuffer {
-        let chunks = thread::available_parallelism()
-            .unwrap_or(NonZeroUsize::MIN)
-            .get()
-            - 1; // Spare one thread for UI (app stutters otherwise)
-        self.delegate_and_run(chunks.max(1) as u32)
-        // TODO: Since regular splitting will be suboptimal here,
-        // maybe implement a smarter algorithm to divide the work?
+    pub fn render_field_parallel(self) -> IterationField {
+        let items = match self.mirror() {
+            Some(mirror) => {
+                self.render_mirrored(mirror, |block| block.render_field().items, |item| item)
+            }
+            None => self.render_blocks(|block| block.render_field().items),
+        };
+        IterationField::new(self.fragment, items)
     }
 }
 
@@ -144,19 +404,15 @@ pub fn take_and_flip(buffer: ImageBuffer) -> Vec<u8> {
         .collect()
 }
 
-pub fn into_data_url(raw: ImageBuffer) -> String {
-    let (width, height) = (raw.width(), raw.height());
+/// Raw RGBA pixels with the top row first, ready to be uploaded as a texture
+pub fn into_rgba(raw: ImageBuffer) -> Vec<u8> {
     let flipped = take_and_flip(raw);
-    let img = ImageBuffer::from_vec(width, height, flipped).unwrap();
-
-    use base64::prelude::*;
-    use std::io::Cursor;
-    let mut buffer = Vec::with_capacity(img.len());
-    let mut cursor = Cursor::new(&mut buffer);
-    img.write_to(&mut cursor, image::ImageOutputFormat::Png)
-        .unwrap();
-    let data = BASE64_STANDARD.encode(buffer);
-    format!("data:image/png;base64,{data}")
+    let mut rgba = Vec::with_capacity(flipped.len() / size_of::<Rgb>() * 4);
+    for pixel in flipped.chunks_exact(size_of::<Rgb>()) {
+        rgba.extend_from_slice(pixel);
+        rgba.push(u8::MAX);
+    }
+    rgba
 }
 
 #[cfg(test)]
@@ -186,38 +442,26 @@ mod tests {
     }
 
     fn mandelbrot() -> FractalImage {
-        FractalImage {
-            color: get_color(),
-            fragment: FRAGMENT,
-            fractal: Fractal::new(1024, Mandelbrot),
-        }
+        FractalImage::new(Fractal::new(1024, Mandelbrot), FRAGMENT, get_color())
     }
     fn julia_set() -> FractalImage {
-        FractalImage {
-            color: get_color(),
-            fragment: FRAGMENT,
-            fractal: Fractal::new(
+        FractalImage::new(
+            Fractal::new(
                 1024,
                 JuliaSet {
                     constant: Complex64::new(0.34, 0.08),
                 },
             ),
-        }
+            FRAGMENT,
+            get_color(),
+        )
     }
     fn burning_ship() -> FractalImage {
-        FractalImage {
-            fragment: FRAGMENT,
-            color: get_color(),
-            fractal: Fractal::new(1024, BurningShip),
-        }
+        FractalImage::new(Fractal::new(1024, BurningShip), FRAGMENT, get_color())
     }
 
     fn newton() -> FractalImage {
-        FractalImage {
-            fragment: FRAGMENT,
-            color: get_color(),
-            fractal: Fractal::new(1024, Newton),
-        }
+        FractalImage::new(Fractal::new(1024, Newton), FRAGMENT, get_color())
     }
 
     #[divan::bench(sample_count = 30)]
@@ -242,17 +486,13 @@ mod tests {
 
     #[divan::bench(sample_count = 20, threads = 1)]
     fn rendered_mandelbrot_threaded() {
-        mandelbrot().render_on_threads();
+        mandelbrot().render_parallel();
     }
 
     #[divan::bench]
-    fn flip_and_encode(bencher: divan::Bencher) {
+    fn flip_into_rgba(bencher: divan::Bencher) {
         let img = mandelbrot().render();
-        bencher.bench(|| {
-            let copy = img.clone();
-            let data = into_data_url(copy);
-            serde_json::to_string(&data).unwrap();
-        })
+        bencher.bench(|| into_rgba(img.clone()))
     }
 
     #[test]
@@ -273,6 +513,13 @@ mod tests {
         assert_eq!(expected_size, got_size);
     }
 
+    #[test]
+    fn converts_to_rgba_top_row_first() {
+        let mut image = ImageBuffer::new(1, 2);
+        image.put_pixel(0, 1, image::Rgb([1, 2, 3]));
+        assert_eq!(into_rgba(image), [1, 2, 3, 255, 0, 0, 0, 255]);
+    }
+
     #[test]
     fn encodes_png() {
         use base64::prelude::*;
@@ -287,6 +534,62 @@ mod tests {
         println!("{}", BASE64_STANDARD.encode(buffer).len());
     }
 
+    #[test]
+    fn mirrors_symmetric_fractals() {
+        for fractal in [mandelbrot(), julia_set()] {
+            assert!(fractal.mirror().is_some());
+            let evaluated = fractal.render_field();
+            let mirrored = fractal.render_field_parallel();
+            let mut items = evaluated.items.iter().zip(&mirrored.items);
+            assert!(items.all(|(a, b)| a.index == b.index && a.value == b.value));
+        }
+        assert!(burning_ship().mirror().is_none());
+    }
+
+    #[test]
+    fn mirrors_rendered_pixels() {
+        for fractal in [mandelbrot(), julia_set()] {
+            assert!(fractal.mirror().is_some());
+            assert_eq!(fractal.render(), fractal.clone().render_parallel());
+        }
+    }
+
+    #[test]
+    fn reuses_samples_in_filled_renders() {
+        let image =
+            crate::fixtures::image(90, 70, Complex64::new(-2.0, 1.3), Complex64::new(1.0, 0.1))
+                .with_filling(Filling::Uniform)
+                .with_strategy(Strategy::Subdivision);
+        let marked = ComplexItem {
+            value: Complex64::new(7.0, 7.0),
+            ..image.eval_at(0, 0)
+        };
+        let image = image.with_samples(vec![marked; 90 * 70], 2);
+        let field = image.render_field_parallel();
+        // Left column of the first and of the second block
+        for (row, sampled) in [(0, true), (1, false), (64, true), (65, false)] {
+            let item = field.items[row * 90];
+            assert_eq!(item.value == marked.value, sampled);
+        }
+    }
+
+    #[test]
+    fn limits_filling_to_fractal() {
+        let filling = |image: FractalImage| image.with_filling(Filling::Uniform).filling;
+        assert_eq!(filling(mandelbrot()), Filling::Uniform);
+        assert_eq!(filling(julia_set()), Filling::Interior);
+        assert_eq!(filling(burning_ship()), Filling::Off);
+        assert_eq!(filling(newton()), Filling::Off);
+    }
+
+    #[test]
+    fn fills_only_with_strategy() {
+        let image = mandelbrot().with_filling(Filling::Uniform);
+        assert_eq!(image.filling(), Filling::Off);
+        let image = image.with_strategy(Strategy::Subdivision);
+        assert_eq!(image.filling(), Filling::Uniform);
+    }
+
     #[test]
     fn render_mandelbrot_saves() {
         mandelbrot().render().save("./gray.png").unwrap();
@@ -300,8 +603,14 @@ mod tests {
     #[test]
     fn render_threaded_saves() {
         burning_ship()
-            .render_on_threads()
+            .render_parallel()
             .save("./threads.png")
             .unwrap();
     }
+
+    #[test]
+    fn renders_same_in_parallel() {
+        let fractal = julia_set();
+        assert_eq!(fractal.render(), fractal.clone().render_parallel());
+    }
 }
@@ -0,0 +1,159 @@
+//! Scene files (`.fractal.json`) - everything needed to restore
+//! a view in the app, or to render it again outside of it.
+//!
+//! Every file carries a schema version. Older files are upgraded
+//! with MIGRATIONS before being deserialized, so that they keep
+//! loading as the data types grow. Files without a version are treated
+//! as version 0 - the RenderParams attached to exported images.
+
+use crate::data::{ColorConfig, FractalConfig, FractalFragment};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{fs, path::Path};
+
+pub const SCENE_VERSION: u64 = 1;
+
+/// MIGRATIONS[i] upgrades a scene from version i to i + 1
+const MIGRATIONS: [fn(&mut Value); SCENE_VERSION as usize] = [from_render_params];
+
+/// Part of the viewport, as fractions of its size (0.0 - 1.0)
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct Selection {
+    pub left: f64,
+    pub top: f64,
+    pub right: f64,
+    pub bottom: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ExportSettings {
+    pub width_px: u32,
+    /// None means the whole viewport will be exported
+    #[serde(default)]
+    pub selection: Option<Selection>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Scene {
+    pub version: u64,
+    pub fractal: FractalConfig,
+    pub color: ColorConfig,
+    pub viewport: FractalFragment,
+    pub export: ExportSettings,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum SceneError {
+    Unreadable,
+    Unwritable,
+    BadFormat,
+    NewerVersion,
+}
+
+fn from_render_params(scene: &mut Value) {
+    let Some(fields) = scene.as_object_mut() else {
+        return;
+    };
+    let viewport = fields.remove("fragment").unwrap_or(Value::Null);
+    let width_px = viewport["width_px"].clone();
+    fields.insert("export".to_owned(), json!({ "width_px": width_px }));
+    fields.insert("viewport".to_owned(), viewport);
+}
+
+impl Scene {
+    pub fn from_json(json: &str) -> Result<Self, SceneError> {
+        let mut scene: Value = serde_json::from_str(json).map_err(|_| SceneError::BadFormat)?;
+        if !scene.is_object() {
+            return Err(SceneError::BadFormat);
+        }
+        let version = scene["version"].as_u64().unwrap_or(0);
+        if version > SCENE_VERSION {
+            return Err(SceneError::NewerVersion);
+        }
+
+        for migrate in &MIGRATIONS[version as usize..] {
+            migrate(&mut scene);
+        }
+        scene["version"] = SCENE_VERSION.into();
+        serde_json::from_value(scene).map_err(|_| SceneError::BadFormat)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SceneError> {
+        let json = fs::read_to_string(path).map_err(|_| SceneError::Unreadable)?;
+        Self::from_json(&json)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SceneError> {
+        let scene = Scene {
+            version: SCENE_VERSION,
+            ..self.clone()
+        };
+        let json = serde_json::to_string_pretty(&scene).map_err(|_| SceneError::BadFormat)?;
+        fs::write(path, json).map_err(|_| SceneError::Unwritable)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scene() -> Scene {
+        Scene::from_json(
+            r##"{
+                "version": 1,
+                "fractal": { "variant": { "type": "Mandelbrot" }, "max_iterations": 256 },
+                "color": {
+                    "color": { "hex_start": "#ff0000", "hex_end": "#ffff00" },
+                    "brightness": 1.0,
+                    "anti_alias": true,
+                    "method": { "type": "Exponential", "power": 0.8 }
+                },
+                "viewport": {
+                    "width_px": 800,
+                    "height_px": 400,
+                    "top_left": { "re": -2.0, "im": 1.0 },
+                    "bottom_right": { "re": 2.0, "im": -1.0 }
+                },
+                "export": {
+                    "width_px": 1000,
+                    "selection": { "left": 0.5, "top": 0.0, "right": 1.0, "bottom": 0.5 }
+                }
+            }"##,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn migrates_unversioned_render_params() {
+        let params = json!({
+            "fractal": scene().fractal,
+            "color": scene().color,
+            "fragment": scene().viewport,
+        });
+        let migrated = Scene::from_json(&params.to_string()).unwrap();
+        assert_eq!(migrated.version, SCENE_VERSION);
+        assert_eq!(migrated.export.width_px, 800);
+        assert!(migrated.export.selection.is_none());
+    }
+
+    #[test]
+    fn refuses_newer_versions() {
+        let json = json!({ "version": SCENE_VERSION + 1 }).to_string();
+        let result = Scene::from_json(&json);
+        assert_eq!(result.err(), Some(SceneError::NewerVersion));
+    }
+
+    #[test]
+    fn refuses_other_json() {
+        for json in ["[]", "1", "\"scene\"", "true", "null"] {
+            assert_eq!(Scene::from_json(json).err(), Some(SceneError::BadFormat));
+        }
+    }
+
+    #[test]
+    fn saves_and_loads() {
+        scene().save("./scene.fractal.json").unwrap();
+        let loaded = Scene::load("./scene.fractal.json").unwrap();
+        assert_eq!(loaded.fractal.max_iterations, 256);
+    }
+}
@@ -0,0 +1,215 @@
+//! Exports of images too big to keep in memory. The image is rendered
+//! in strips of rows, which go to the encoder as soon as they are ready,
+//! so memory use depends on the strip size, not on the size of the image.
+
+use crate::{
+    data::{ExportRequest, ExportResult, FractalFragment, RenderParams},
+    metadata::{sidecar_path, PARAMS_KEYWORD},
+    progress::Progress,
+    renderer::FractalImage,
+};
+use std::{
+    error::Error,
+    fs::{self, File},
+    io::{self, BufWriter, Seek, Write},
+    path::Path,
+};
+use tiff::encoder::{colortype::RGB8, TiffEncoder, TiffKind};
+
+/// Memory taken by the pixels of a single strip
+pub const STRIP_BYTES: u64 = 64 << 20;
+/// Room for headers, chunks and metadata
+const OVERHEAD_BYTES: u64 = 1 << 20;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum StreamFormat {
+    Png,
+    Tiff,
+}
+
+impl StreamFormat {
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?;
+        match extension.to_lowercase().as_str() {
+            "png" => Some(Self::Png),
+            "tif" | "tiff" => Some(Self::Tiff),
+            _ => None,
+        }
+    }
+}
+
+/// Strips come from the bottom of the image, as raw RGB pixels
+pub type Strips<'a> = Box<dyn Iterator<Item = io::Result<Vec<u8>>> + 'a>;
+
+pub fn strip_rows(width_px: u32) -> u32 {
+    let row_bytes = width_px.max(1) as u64 * 3;
+    (STRIP_BYTES / row_bytes).clamp(1, u32::MAX as u64) as u32
+}
+
+/// Upper bound of the file size - uncompressed pixels, PNG only gets smaller
+pub fn estimated_size(fragment: &FractalFragment) -> u64 {
+    fragment.width_px as u64 * fragment.height_px as u64 * 3 + OVERHEAD_BYTES
+}
+
+/// Assumes there is space, when it can't be checked
+pub fn has_space_for(path: &Path, bytes: u64) -> bool {
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    fs2::available_space(directory).map_or(true, |available| available >= bytes)
+}
+
+fn stream_png(
+    strips: Strips,
+    fragment: &FractalFragment,
+    path: &Path,
+    params: String,
+) -> Result<(), Box<dyn Error>> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, fragment.width_px, fragment.height_px);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.add_itxt_chunk(PARAMS_KEYWORD.to_owned(), params)?;
+    let mut writer = encoder.write_header()?;
+    let mut stream = writer.stream_writer()?;
+    for strip in strips {
+        stream.write_all(&strip?)?;
+    }
+    stream.finish()?;
+    Ok(())
+}
+
+fn write_tiff_strips<W: Write + Seek, K: TiffKind>(
+    mut encoder: TiffEncoder<W, K>,
+    strips: Strips,
+    fragment: &FractalFragment,
+) -> Result<(), Box<dyn Error>> {
+    let mut tiff = encoder.new_image::<RGB8>(fragment.width_px, fragment.height_px)?;
+    tiff.rows_per_strip(strip_rows(fragment.width_px))?;
+    for strip in strips {
+        tiff.write_strip(&strip?)?;
+    }
+    tiff.finish()?;
+    Ok(())
+}
+
+fn stream_tiff(
+    strips: Strips,
+    fragment: &FractalFragment,
+    path: &Path,
+    params: String,
+) -> Result<(), Box<dyn Error>> {
+    let file = BufWriter::new(File::create(path)?);
+    // Regular TIFFs address at most 4 GB
+    match estimated_size(fragment) < u32::MAX as u64 {
+        true => write_tiff_strips(TiffEncoder::new(file)?, strips, fragment)?,
+        false => write_tiff_strips(TiffEncoder::new_big(file)?, strips, fragment)?,
+    };
+    fs::write(sidecar_path(path), params)?;
+    Ok(())
+}
+
+/// Encodes strips of strip_rows(width) rows, as they come
+pub fn save_strips(
+    strips: Strips,
+    request: &ExportRequest,
+    format: StreamFormat,
+) -> Result<(), Box<dyn Error>> {
+    let params = serde_json::to_string(&RenderParams::from(request))?;
+    let path = Path::new(&request.filepath);
+    match format {
+        StreamFormat::Png => stream_png(strips, &request.fragment, path, params),
+        StreamFormat::Tiff => stream_tiff(strips, &request.fragment, path, params),
+    }
+}
+
+pub fn export_streamed(
+    request: ExportRequest,
+    format: StreamFormat,
+    progress: &Progress,
+) -> ExportResult {
+    let path = Path::new(&request.filepath);
+    if !has_space_for(path, estimated_size(&request.fragment)) {
+        return ExportResult::ErrorNoDiskSpace;
+    }
+
+    let image = FractalImage::from(request.clone()).with_progress(progress.clone());
+    let rows = strip_rows(request.fragment.width_px);
+    let strips = image
+        .into_strips(rows)
+        .map(|strip| Ok(strip.render_parallel().into_raw()));
+    match save_strips(Box::new(strips), &request, format) {
+        Err(_) => ExportResult::ErrorUnknown,
+        Ok(_) => ExportResult::Done,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fixtures::{export_request, peak_allocation},
+        metadata::read_params,
+        renderer::Strategy,
+    };
+    use num::complex::Complex64;
+
+    #[test]
+    fn strips_cover_whole_image() {
+        let image: FractalImage = export_request("").into();
+        let whole = image.clone().render();
+        let strips: Vec<u8> = image
+            .into_strips(7)
+            .flat_map(|strip| strip.render_parallel().into_raw())
+            .collect();
+        assert_eq!(strips, whole.into_raw());
+    }
+
+    #[test]
+    fn renders_strips_across_axis_as_pixels() {
+        // The real axis runs through row 160, which gets mirrored
+        let fragment = FractalFragment {
+            width_px: 400,
+            height_px: 300,
+            top_left: Complex64::new(-2.0, 1.05),
+            bottom_right: Complex64::new(1.0, -1.2),
+        };
+        let pixel_bytes = 400 * 300 * 3;
+        for strategy in [Strategy::Off, Strategy::Subdivision] {
+            let request = ExportRequest {
+                fragment: fragment.clone(),
+                strategy,
+                ..export_request("")
+            };
+            let image: FractalImage = request.into();
+            let mut strips = image.into_strips(strip_rows(400));
+            let strip = strips.next().unwrap();
+            let (pixels, peak) = peak_allocation(|| strip.render_parallel());
+            assert_eq!(pixels.len(), pixel_bytes);
+            // The pixels, and their copy while they are put together
+            assert!(peak <= 2 * pixel_bytes, "{peak} bytes");
+        }
+    }
+
+    #[test]
+    fn streams_png_with_params() {
+        let request = export_request("./streamed.png");
+        let whole: FractalImage = request.clone().into();
+        let result = export_streamed(request, StreamFormat::Png, &Progress::default());
+        assert!(matches!(result, ExportResult::Done));
+
+        let saved = image::open("./streamed.png").unwrap().into_rgb8();
+        assert_eq!(saved, whole.render_parallel());
+        assert!(read_params("./streamed.png").is_some());
+    }
+
+    #[test]
+    fn streams_tiff() {
+        let request = export_request("./streamed.tiff");
+        let result = export_streamed(request, StreamFormat::Tiff, &Progress::default());
+        assert!(matches!(result, ExportResult::Done));
+        let saved = image::open("./streamed.tiff").unwrap();
+        assert_eq!((saved.width(), saved.height()), (40, 30));
+    }
+}
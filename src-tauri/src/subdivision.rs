@@ -0,0 +1,179 @@
+//! Mariani-Silver rendering. The border of a rectangle is evaluated first,
+//! and when all of it has the same iteration count, the interior is filled
+//! with a copy of the border, without evaluating it. Other rectangles are
+//! split in half along their longer side, until they are small enough
+//! to evaluate every pixel. Borders outside the set enclose only their
+//! escape band, unless they go around the whole set, which contains zero.
+
+use crate::{
+    fractal::ComplexItem,
+    progress::Progress,
+    renderer::{Filling, FractalImage},
+};
+use num::complex::Complex64;
+
+/// Rectangles this narrow are evaluated pixel by pixel
+const MIN_SIZE: u32 = 4;
+
+struct Grid<'a> {
+    image: &'a FractalImage,
+    filling: Filling,
+    progress: Option<&'a Progress>,
+    width: u32,
+    items: Vec<Option<ComplexItem>>,
+}
+
+impl Grid<'_> {
+    fn eval(&mut self, x: u32, row: u32) -> ComplexItem {
+        let id = (row * self.width + x) as usize;
+        *self.items[id].get_or_insert_with(|| self.image.eval_at(x, row))
+    }
+
+    /// Renders the rectangle between the corners, both of them included
+    fn rectangle(&mut self, (x0, y0): (u32, u32), (x1, y1): (u32, u32)) {
+        if self.progress.is_some_and(Progress::is_cancelled) {
+            return;
+        }
+        if x1 - x0 < MIN_SIZE || y1 - y0 < MIN_SIZE {
+            for row in y0..=y1 {
+                for x in x0..=x1 {
+                    self.eval(x, row);
+                }
+            }
+            return;
+        }
+
+        let corner = self.eval(x0, y0);
+        let mut uniform = true;
+        for x in x0..=x1 {
+            uniform &= self.eval(x, y0).index == corner.index;
+            uniform &= self.eval(x, y1).index == corner.index;
+        }
+        for row in y0..=y1 {
+            uniform &= self.eval(x0, row).index == corner.index;
+            uniform &= self.eval(x1, row).index == corner.index;
+        }
+        let inside = corner.index == corner.max_index;
+        let around_set = !inside
+            && self
+                .image
+                .encloses(Complex64::default(), (x0, y0), (x1, y1));
+        if uniform && self.filling.fills(&corner) && !around_set {
+            for row in y0 + 1..y1 {
+                let first = (row * self.width) as usize;
+                let interior = first + x0 as usize + 1..first + x1 as usize;
+                self.items[interior].fill(Some(corner));
+            }
+            return;
+        }
+
+        // Halves share the line between them, it's evaluated only once
+        if x1 - x0 >= y1 - y0 {
+            let middle = (x0 + x1) / 2;
+            self.rectangle((x0, y0), (middle, y1));
+            self.rectangle((middle, y0), (x1, y1));
+        } else {
+            let middle = (y0 + y1) / 2;
+            self.rectangle((x0, y0), (x1, middle));
+            self.rectangle((x0, middle), (x1, y1));
+        }
+    }
+}
+
+/// Items of the image, row by row from the bottom. Cancelled renders
+/// stop between rectangles and leave the rest unevaluated
+pub fn render(
+    image: &FractalImage,
+    filling: Filling,
+    progress: Option<&Progress>,
+) -> Vec<ComplexItem> {
+    let fragment = image.fragment();
+    let (width, height) = (fragment.width_px, fragment.height_px);
+    let mut grid = Grid {
+        image,
+        filling,
+        progress,
+        width,
+        items: vec![None; width as usize * height as usize],
+    };
+    if width > 0 && height > 0 {
+        grid.rectangle((0, 0), (width - 1, height - 1));
+    }
+    if let Some(progress) = progress.filter(|progress| !progress.is_cancelled()) {
+        (0..height).for_each(|_| progress.row_done());
+    }
+    let unevaluated = image.unevaluated();
+    grid.items
+        .into_iter()
+        .map(|item| item.unwrap_or(unevaluated))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fixtures, renderer::Strategy};
+    use num::complex::Complex64;
+
+    fn image(filling: Filling, top_left: Complex64, bottom_right: Complex64) -> FractalImage {
+        fixtures::image(90, 70, top_left, bottom_right)
+            .with_filling(filling)
+            .with_strategy(Strategy::Subdivision)
+    }
+
+    fn whole_set(filling: Filling) -> FractalImage {
+        image(
+            filling,
+            Complex64::new(-2.0, 1.2),
+            Complex64::new(1.0, -1.2),
+        )
+    }
+
+    fn indices(items: &[ComplexItem]) -> Vec<f64> {
+        items.iter().map(|item| item.index).collect()
+    }
+
+    #[test]
+    fn matches_full_render() {
+        let image = whole_set(Filling::Off);
+        let expected: Vec<_> = (0..70)
+            .flat_map(|row| (0..90).map(move |x| (x, row)))
+            .map(|(x, row)| image.eval_at(x, row).index)
+            .collect();
+        for filling in [Filling::Interior, Filling::Uniform] {
+            let subdivided = render(&whole_set(filling), filling, None);
+            assert_eq!(indices(&subdivided), expected);
+        }
+    }
+
+    #[test]
+    fn fills_uniform_interior() {
+        let (top_left, bottom_right) = (Complex64::new(-0.3, 0.2), Complex64::new(0.0, -0.2));
+        let subdivided = image(Filling::Interior, top_left, bottom_right).render_field();
+        let full = image(Filling::Off, top_left, bottom_right).render_field();
+        let corner = subdivided.items[0];
+        let center = 35 * 90 + 45;
+        assert_eq!(subdivided.items[center].value, corner.value);
+        assert_ne!(full.items[center].value, corner.value);
+    }
+
+    #[test]
+    fn keeps_set_inside_uniform_band() {
+        let (top_left, bottom_right) = (Complex64::new(-20.0, 15.0), Complex64::new(20.0, -15.0));
+        let filled = image(Filling::Uniform, top_left, bottom_right).render_field();
+        let full = image(Filling::Off, top_left, bottom_right).render_field();
+        assert_eq!(indices(&filled.items), indices(&full.items));
+    }
+
+    #[test]
+    fn stops_when_cancelled() {
+        let progress = Progress::default();
+        progress.cancel();
+        let items = render(
+            &whole_set(Filling::Uniform),
+            Filling::Uniform,
+            Some(&progress),
+        );
+        assert!(items.iter().all(|item| item.index == 0.0));
+    }
+}
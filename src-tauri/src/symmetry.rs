@@ -0,0 +1,250 @@
+//! Fragments straddling the axis of symmetry of their fractal are rendered
+//! only on the larger side of the axis, the other side is mirrored. Pixels
+//! are only mirrored onto pixels, so the axis has to run through the middle
+//! of a row, or exactly between two rows. Fragments on one side of the axis,
+//! like tiles, can be mirrored from a rendered fragment on the other side.
+
+use crate::{
+    data::{FractalFragment, Rgb},
+    fractal::ComplexItem,
+};
+use num::complex::Complex64;
+use std::ops::Range;
+
+/// How far (in pixels) the axis can be off the grid, and still count as on it
+const ALIGNMENT: f64 = 1e-6;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Symmetry {
+    /// Conjugate points look the same (polynomials with real coefficients)
+    RealAxis,
+    /// Opposite points look the same (Julia sets)
+    Origin,
+}
+
+impl Symmetry {
+    fn mirror(self, item: &ComplexItem) -> ComplexItem {
+        let value = match self {
+            Self::RealAxis => item.value.conj(),
+            // Orbits of opposite points meet after the first iteration
+            Self::Origin if item.index == 0.0 => -item.value,
+            Self::Origin => item.value,
+        };
+        ComplexItem { value, ..*item }
+    }
+
+    /// Fragment of the same size on the other side of the axis (or of
+    /// the origin). Pixels are sampled at their bottom left corners, so
+    /// its samples are mirror images of the samples a pixel further
+    pub fn mirror_fragment(self, fragment: &FractalFragment) -> FractalFragment {
+        // Subtracted from zero, which stays positive, unlike -0.0
+        let negate = |value: f64| 0.0 - value;
+        let (top_left, bottom_right) = (fragment.top_left, fragment.bottom_right);
+        let (left, right) = match self {
+            Self::RealAxis => (top_left.re, bottom_right.re),
+            Self::Origin => (negate(bottom_right.re), negate(top_left.re)),
+        };
+        FractalFragment {
+            width_px: fragment.width_px,
+            height_px: fragment.height_px,
+            top_left: Complex64::new(left, negate(bottom_right.im)),
+            bottom_right: Complex64::new(right, negate(top_left.im)),
+        }
+    }
+
+    /// Items of the fragment, mirrored from the items of its mirror fragment.
+    /// The bottom row (and the left column) have no mirror images in there,
+    /// they are evaluated
+    pub fn unmirror(
+        self,
+        fragment: &FractalFragment,
+        mirrored: &[ComplexItem],
+        eval: impl Fn(u32, u32) -> ComplexItem,
+    ) -> Vec<ComplexItem> {
+        let (width, height) = (fragment.width_px, fragment.height_px);
+        let mut items = Vec::with_capacity(width as usize * height as usize);
+        for row in 0..height {
+            for x in 0..width {
+                let column = match self {
+                    Self::RealAxis => Some(x),
+                    Self::Origin => Some(width - x).filter(|_| x > 0),
+                };
+                let source = column
+                    .filter(|_| row > 0)
+                    .map(|column| ((height - row) * width + column) as usize);
+                items.push(match source {
+                    Some(id) => self.mirror(&mirrored[id]),
+                    None => eval(x, row),
+                });
+            }
+        }
+        items
+    }
+}
+
+/// What the pixels of a render hold, copied onto their mirror images
+pub trait Mirrored: Clone {
+    fn mirrored(&self, symmetry: Symmetry) -> Self;
+}
+
+impl Mirrored for ComplexItem {
+    fn mirrored(&self, symmetry: Symmetry) -> Self {
+        symmetry.mirror(self)
+    }
+}
+
+/// Colors depend only on the iteration count and on the magnitude
+/// of the value, which mirror images share
+impl Mirrored for Rgb {
+    fn mirrored(&self, _: Symmetry) -> Self {
+        *self
+    }
+}
+
+/// Twice the position of zero on the pixel grid, counted from `start`,
+/// when zero lies on the grid
+fn doubled_zero(start: f64, step: f64) -> Option<i64> {
+    let doubled = -2.0 * start / step;
+    let rounded = doubled.round();
+    Some(rounded as i64).filter(|_| (doubled - rounded).abs() < 2.0 * ALIGNMENT)
+}
+
+#[derive(Clone, Debug)]
+pub struct Mirror {
+    symmetry: Symmetry,
+    /// Doubled, so that an axis between two rows stays an integer
+    doubled_row_axis: i64,
+    doubled_column_axis: Option<i64>,
+    /// Rows evaluated, the rest of them is mirrored
+    pub rows: Range<u32>,
+}
+
+impl Mirror {
+    /// Nothing, when the fragment doesn't straddle the axis
+    pub fn find(fragment: &FractalFragment, step: f64, symmetry: Symmetry) -> Option<Self> {
+        let height = fragment.height_px as i64;
+        let doubled_row_axis = doubled_zero(fragment.bottom_right.im, step)?;
+        if doubled_row_axis <= 0 || doubled_row_axis >= 2 * (height - 1) {
+            return None;
+        }
+        let doubled_column_axis = match symmetry {
+            Symmetry::RealAxis => None,
+            Symmetry::Origin => Some(doubled_zero(fragment.top_left.re, step)?),
+        };
+
+        // Rows strictly below and strictly above the axis
+        let below = (doubled_row_axis + 1) / 2;
+        let above = height - doubled_row_axis / 2 - 1;
+        let rows = match below <= above {
+            true => below as u32..height as u32,
+            false => 0..(height - above) as u32,
+        };
+        Some(Self {
+            symmetry,
+            doubled_row_axis,
+            doubled_column_axis,
+            rows,
+        })
+    }
+
+    /// Number of rows, which aren't evaluated
+    pub fn mirrored_rows(&self, fragment: &FractalFragment) -> u32 {
+        fragment.height_px - self.rows.len() as u32
+    }
+
+    /// Completes the pixels of the evaluated rows into the whole fragment.
+    /// Pixels, whose mirror images fall outside of it, are evaluated
+    pub fn unfold<T: Mirrored>(
+        &self,
+        fragment: &FractalFragment,
+        evaluated: Vec<T>,
+        eval: impl Fn(u32, u32) -> T,
+    ) -> Vec<T> {
+        let width = fragment.width_px as usize;
+        let mut items = Vec::with_capacity(width * fragment.height_px as usize);
+        let row_of = |row: u32| {
+            let first = (row - self.rows.start) as usize * width;
+            &evaluated[first..first + width]
+        };
+        for row in 0..fragment.height_px {
+            if self.rows.contains(&row) {
+                items.extend_from_slice(row_of(row));
+                continue;
+            }
+            let source = row_of((self.doubled_row_axis - row as i64) as u32);
+            for x in 0..fragment.width_px {
+                let column = match self.doubled_column_axis {
+                    None => Some(x as usize),
+                    Some(axis) => usize::try_from(axis - x as i64).ok(),
+                };
+                items.push(match column.filter(|&column| column < width) {
+                    Some(column) => source[column].mirrored(self.symmetry),
+                    None => eval(x, row),
+                });
+            }
+        }
+        items
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num::complex::Complex64;
+
+    fn fragment(bottom: f64, left: f64) -> FractalFragment {
+        FractalFragment {
+            width_px: 8,
+            height_px: 10,
+            top_left: Complex64::new(left, bottom + 10.0),
+            bottom_right: Complex64::new(left + 8.0, bottom),
+        }
+    }
+
+    #[test]
+    fn evaluates_larger_side() {
+        // Axis on row 3, rows 0..3 are mirrored from rows 4..7
+        let mirror = Mirror::find(&fragment(-3.0, 0.0), 1.0, Symmetry::RealAxis).unwrap();
+        assert_eq!(mirror.rows, 3..10);
+        // Axis between rows 6 and 7, rows 7..10 are mirrored from rows 4..7
+        let mirror = Mirror::find(&fragment(-6.5, 0.0), 1.0, Symmetry::RealAxis).unwrap();
+        assert_eq!(mirror.rows, 0..7);
+    }
+
+    #[test]
+    fn mirrors_whole_fragments() {
+        use crate::{
+            color::ColorCreator, data::*, fixtures, fractal::Fractal, renderer::FractalImage,
+        };
+        let color: ColorCreator = fixtures::color_config().into();
+        let constant = Complex64::new(-0.4, 0.6);
+        let fractals = [
+            (FractalVariant::Mandelbrot, Symmetry::RealAxis),
+            (FractalVariant::JuliaSet { constant }, Symmetry::Origin),
+        ];
+        for (variant, symmetry) in fractals {
+            let image = |fragment| FractalImage::new(Fractal::new(64, variant), fragment, color);
+            let fragment = FractalFragment {
+                width_px: 16,
+                height_px: 8,
+                top_left: Complex64::new(-1.0, 1.0),
+                bottom_right: Complex64::new(1.0, 0.0),
+            };
+            let mirrored = image(symmetry.mirror_fragment(&fragment)).render_field();
+            let direct = image(fragment.clone());
+            let items =
+                symmetry.unmirror(&fragment, &mirrored.items, |x, row| direct.eval_at(x, row));
+            let expected = direct.render_field().items;
+            let mut pairs = items.iter().zip(&expected);
+            assert!(pairs.all(|(a, b)| a.index == b.index && a.value == b.value));
+        }
+    }
+
+    #[test]
+    fn needs_aligned_axis() {
+        assert!(Mirror::find(&fragment(-3.3, 0.0), 1.0, Symmetry::RealAxis).is_none());
+        assert!(Mirror::find(&fragment(1.0, 0.0), 1.0, Symmetry::RealAxis).is_none());
+        assert!(Mirror::find(&fragment(-3.0, -0.2), 1.0, Symmetry::Origin).is_none());
+        assert!(Mirror::find(&fragment(-3.0, -0.5), 1.0, Symmetry::Origin).is_some());
+    }
+}
@@ -0,0 +1,503 @@
+//! Tiles are rendered by a few workers, in the order of their priorities,
+//! which the frontend updates as the viewport moves. Tiles, which left
+//! the screen or were requested for an outdated config, are cancelled
+//! and stop between rows, instead of computing pixels nobody will see.
+//! Rendered tiles wait in the store as raw RGBA pixels, until the frontend
+//! fetches them through the `tile://` protocol, or cancels them.
+
+use crate::{
+    cache::FieldCache,
+    color::ColorCreator,
+    data::{ColorConfig, FractalConfig, FractalFragment, TileBatch, TileId, TileRequest},
+    field::IterationField,
+    fractal::Fractal,
+    progress::Progress,
+    progressive,
+    renderer::{into_rgba, FractalImage, Strategy},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Condvar, Mutex},
+    thread,
+};
+
+/// Tiles rendered at the same time, each of them on the whole render pool
+pub const TILE_WORKERS: usize = 2;
+/// Rendered tiles nobody fetched are dropped after that many newer ones
+pub const MAX_STORED_TILES: usize = 64;
+/// Progressive tiles store a pass per step until they are finished
+const MAX_STORED_PASSES: usize = MAX_STORED_TILES * progressive::STEPS.len();
+pub const PROTOCOL: &str = "tile";
+
+#[derive(Default)]
+struct Tiles {
+    running: HashMap<(u32, TileId), (u32, Progress)>,
+    /// Ids and generations restart with every session, tiles of other
+    /// sessions are cancelled right away
+    session: u32,
+    /// Tiles of older generations are cancelled right away
+    generation: u32,
+}
+
+#[derive(Default)]
+pub struct RunningTiles(Mutex<Tiles>);
+
+impl RunningTiles {
+    /// Starts over from the first generation, when the frontend (re)loads.
+    /// Cancels all running tiles and returns the new session
+    pub fn start_session(&self) -> u32 {
+        let mut tiles = self.0.lock().unwrap();
+        tiles.session += 1;
+        tiles.generation = 0;
+        for (_, progress) in tiles.running.values() {
+            progress.cancel();
+        }
+        tiles.session
+    }
+
+    pub fn session(&self) -> u32 {
+        self.0.lock().unwrap().session
+    }
+
+    /// Progress of the tile render, cancelled together with the tile
+    pub fn start(&self, session: u32, id: TileId, generation: u32) -> Progress {
+        let mut tiles = self.0.lock().unwrap();
+        let progress = Progress::default();
+        if session != tiles.session || generation < tiles.generation {
+            progress.cancel();
+        }
+        let running = (generation, progress.clone());
+        tiles.running.insert((session, id), running);
+        progress
+    }
+
+    pub fn finish(&self, session: u32, id: TileId) {
+        self.0.lock().unwrap().running.remove(&(session, id));
+    }
+
+    /// Cancels the listed tiles of the session, and all tiles older than
+    /// the generation. Returns the number of cancelled renders
+    pub fn cancel(&self, ids: &[TileId], generation: u32) -> usize {
+        let mut tiles = self.0.lock().unwrap();
+        tiles.generation = tiles.generation.max(generation);
+        let (session, oldest_valid) = (tiles.session, tiles.generation);
+        let mut cancelled = 0;
+        for ((tile_session, id), (generation, progress)) in &tiles.running {
+            if *tile_session == session && (ids.contains(id) || *generation < oldest_valid) {
+                progress.cancel();
+                cancelled += 1;
+            }
+        }
+        cancelled
+    }
+}
+
+/// Everything tiles of a batch share, prepared once for all of them
+pub struct TileSetup {
+    config: FractalConfig,
+    fractal: Fractal,
+    color: ColorCreator,
+    strategy: Strategy,
+}
+
+impl TileSetup {
+    pub fn new(config: FractalConfig, color: ColorConfig, strategy: Strategy) -> Self {
+        Self {
+            fractal: config.clone().into(),
+            color: color.into(),
+            config,
+            strategy,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct QueuedTile {
+    pub id: TileId,
+    pub session: u32,
+    pub generation: u32,
+    pub fragment: FractalFragment,
+    /// Tiles with lower priorities are rendered first
+    pub priority: f64,
+    pub progressive: bool,
+    pub setup: Arc<TileSetup>,
+}
+
+impl From<TileRequest> for QueuedTile {
+    fn from(request: TileRequest) -> Self {
+        Self {
+            id: request.id,
+            session: request.session,
+            generation: request.generation,
+            fragment: request.fragment,
+            priority: 0.0,
+            progressive: request.progressive,
+            setup: Arc::new(TileSetup::new(
+                request.fractal,
+                request.color,
+                request.strategy,
+            )),
+        }
+    }
+}
+
+impl TileBatch {
+    pub fn into_queued(self) -> Vec<QueuedTile> {
+        let setup = Arc::new(TileSetup::new(self.fractal, self.color, self.strategy));
+        let (session, generation) = (self.session, self.generation);
+        let progressive = self.progressive;
+        self.tiles
+            .into_iter()
+            .map(|tile| QueuedTile {
+                id: tile.id,
+                session,
+                generation,
+                fragment: tile.fragment,
+                priority: tile.priority,
+                progressive,
+                setup: setup.clone(),
+            })
+            .collect()
+    }
+}
+
+/// Renders the tile into RGBA pixels, unless it gets cancelled.
+/// Progressive tiles pass their coarse passes to `on_pass` on the way
+pub fn render_tile(
+    tile: QueuedTile,
+    cache: &FieldCache,
+    tiles: &RunningTiles,
+    mut on_pass: impl FnMut(u32, Vec<u8>),
+) -> Option<Vec<u8>> {
+    let progress = tiles.start(tile.session, tile.id, tile.generation);
+    let setup = &tile.setup;
+    let image = FractalImage::new(setup.fractal.clone(), tile.fragment.clone(), setup.color)
+        .with_filling(setup.color.filling())
+        .with_strategy(setup.strategy)
+        .with_progress(progress.clone());
+    let filling = image.filling();
+    let field = cache.get_or_render(&setup.config, &tile.fragment, filling, || {
+        // Tiles never straddle the axis, but their mirror images may be cached
+        let mirrored = setup.fractal.symmetry().and_then(|symmetry| {
+            let fragment = symmetry.mirror_fragment(&tile.fragment);
+            let field = cache.cached(&setup.config, &fragment, filling)?;
+            let eval = |x, row| image.eval_at(x, row);
+            let items = symmetry.unmirror(&tile.fragment, &field.items, eval);
+            Some(IterationField::new(tile.fragment.clone(), items))
+        });
+        if mirrored.is_some() {
+            return mirrored;
+        }
+        if tile.progressive {
+            return progressive::render(&image, &progress, |step, field| {
+                on_pass(step, into_rgba(field.colorize(&setup.color)))
+            });
+        }
+        let field = image.render_field_parallel();
+        Some(field).filter(|_| !progress.is_cancelled())
+    });
+    tiles.finish(tile.session, tile.id);
+    field.map(|field| into_rgba(field.colorize(&setup.color)))
+}
+
+/// Session and id of the tile, and the spacing of the samples of the pass
+pub type PassKey = (u32, TileId, u32);
+
+struct StoredPass {
+    key: PassKey,
+    generation: u32,
+    pixels: Vec<u8>,
+}
+
+#[derive(Default)]
+pub struct TileStore(Mutex<VecDeque<StoredPass>>);
+
+impl TileStore {
+    /// Finished tiles replace their coarse passes, which are outdated
+    pub fn insert(&self, key: PassKey, generation: u32, pixels: Vec<u8>) {
+        let mut tiles = self.0.lock().unwrap();
+        let (session, id, step) = key;
+        tiles.retain(|stored| match step {
+            1 => (stored.key.0, stored.key.1) != (session, id),
+            _ => stored.key != key,
+        });
+        tiles.push_back(StoredPass {
+            key,
+            generation,
+            pixels,
+        });
+        if tiles.len() > MAX_STORED_PASSES {
+            tiles.pop_front();
+        }
+    }
+
+    /// Tiles are served once, then they are gone
+    pub fn take(&self, key: PassKey) -> Option<Vec<u8>> {
+        let mut tiles = self.0.lock().unwrap();
+        let position = tiles.iter().position(|stored| stored.key == key)?;
+        tiles.remove(position).map(|stored| stored.pixels)
+    }
+
+    /// Drops passes of other sessions, of the listed tiles, and of tiles
+    /// older than the generation, nobody is going to fetch them
+    pub fn cancel(&self, session: u32, ids: &[TileId], generation: u32) {
+        self.0.lock().unwrap().retain(|stored| {
+            let (stored_session, id, _) = stored.key;
+            stored_session == session && !ids.contains(&id) && stored.generation >= generation
+        });
+    }
+}
+
+/// Passes are requested as `tile://localhost/<session>-<id>-<step>`,
+/// or as `https://tile.localhost/<session>-<id>-<step>` on Windows
+pub fn parse_tile_uri(uri: &str) -> Option<PassKey> {
+    let name = uri.trim_end_matches('/').rsplit('/').next()?;
+    let (session, name) = name.split_once('-')?;
+    let (id, step) = name.split_once('-')?;
+    Some((session.parse().ok()?, id.parse().ok()?, step.parse().ok()?))
+}
+
+#[derive(Deserialize, Clone, Copy)]
+pub struct TilePriority {
+    pub id: TileId,
+    pub priority: f64,
+}
+
+#[derive(Serialize, Clone)]
+pub struct RenderedTile {
+    pub id: TileId,
+    pub session: u32,
+    pub generation: u32,
+    /// Spacing of the samples, 1 for the finished tile
+    pub step: u32,
+    /// False, when the tile was cancelled
+    pub ready: bool,
+}
+
+#[derive(Clone, Default)]
+pub struct TileQueue {
+    queued: Arc<(Mutex<Vec<QueuedTile>>, Condvar)>,
+}
+
+impl TileQueue {
+    /// Starts the workers, which pass the tiles to render
+    pub fn new(workers: usize, render: impl Fn(QueuedTile) + Send + Sync + 'static) -> Self {
+        let queue = Self::default();
+        let render = Arc::new(render);
+        for _ in 0..workers {
+            let queue = queue.clone();
+            let render = render.clone();
+            thread::spawn(move || loop {
+                render(queue.pop());
+            });
+        }
+        queue
+    }
+
+    /// Waits for the tile with the lowest priority
+    fn pop(&self) -> QueuedTile {
+        let (queued, scheduled) = &*self.queued;
+        let mut queued = queued.lock().unwrap();
+        loop {
+            let first = queued
+                .iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| a.priority.total_cmp(&b.priority))
+                .map(|(id, _)| id);
+            if let Some(id) = first {
+                return queued.remove(id);
+            }
+            queued = scheduled.wait(queued).unwrap();
+        }
+    }
+
+    pub fn schedule(&self, tiles: Vec<QueuedTile>) {
+        let (queued, scheduled) = &*self.queued;
+        queued.lock().unwrap().extend(tiles);
+        scheduled.notify_all();
+    }
+
+    /// Tiles missing from the list keep their priorities
+    pub fn prioritize(&self, priorities: &[TilePriority]) {
+        let priorities: HashMap<_, _> = priorities
+            .iter()
+            .map(|tile| (tile.id, tile.priority))
+            .collect();
+        let mut queued = self.queued.0.lock().unwrap();
+        for tile in queued.iter_mut() {
+            if let Some(&priority) = priorities.get(&tile.id) {
+                tile.priority = priority;
+            }
+        }
+    }
+
+    /// Drops all the tiles, when a new session starts
+    pub fn clear(&self) -> usize {
+        self.queued.0.lock().unwrap().drain(..).count()
+    }
+
+    /// Drops the listed tiles, and all tiles older than the generation.
+    /// Returns the number of dropped tiles
+    pub fn cancel(&self, ids: &[TileId], generation: u32) -> usize {
+        let mut queued = self.queued.0.lock().unwrap();
+        let before = queued.len();
+        queued.retain(|tile| !ids.contains(&tile.id) && tile.generation >= generation);
+        before - queued.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{data::BatchTile, fixtures, symmetry::Symmetry};
+    use num::complex::Complex64;
+    use std::sync::mpsc;
+
+    fn batch(priorities: &[f64]) -> TileBatch {
+        let tiles = priorities
+            .iter()
+            .enumerate()
+            .map(|(id, &priority)| BatchTile {
+                id: id as TileId,
+                fragment: FractalFragment {
+                    width_px: 8,
+                    height_px: 8,
+                    top_left: Complex64::new(-2.0 + id as f64, 1.0),
+                    bottom_right: Complex64::new(-1.0 + id as f64, 0.0),
+                },
+                priority,
+            });
+        TileBatch {
+            fractal: fixtures::fractal_config(),
+            color: fixtures::color_config(),
+            session: 0,
+            generation: 0,
+            progressive: false,
+            strategy: Strategy::BoundaryTracing,
+            tiles: tiles.collect(),
+        }
+    }
+
+    #[test]
+    fn cancels_by_id_and_generation() {
+        let tiles = RunningTiles::default();
+        let old = tiles.start(0, 0, 0);
+        let listed = tiles.start(0, 1, 1);
+        let current = tiles.start(0, 2, 1);
+        assert_eq!(tiles.cancel(&[1], 1), 2);
+        assert!(old.is_cancelled() && listed.is_cancelled());
+        assert!(!current.is_cancelled());
+
+        tiles.finish(0, 2);
+        assert!(tiles.start(0, 3, 0).is_cancelled());
+    }
+
+    #[test]
+    fn starts_over_in_new_session() {
+        let tiles = RunningTiles::default();
+        tiles.cancel(&[], 5);
+        let running = tiles.start(0, 0, 5);
+        let session = tiles.start_session();
+        assert!(running.is_cancelled());
+        assert!(!tiles.start(session, 0, 0).is_cancelled());
+        assert!(tiles.start(0, 1, 5).is_cancelled());
+
+        // Old tiles finishing late leave new ones with the same id alone
+        tiles.finish(0, 0);
+        assert_eq!(tiles.cancel(&[0], 0), 1);
+    }
+
+    #[test]
+    fn serves_stored_tiles_once() {
+        let store = TileStore::default();
+        store.insert((2, 7, 1), 0, vec![1, 2, 3, 4]);
+        store.insert((2, 7, 8), 0, vec![5, 6, 7, 8]);
+        let key = parse_tile_uri("https://tile.localhost/2-7-1").unwrap();
+        assert_eq!(parse_tile_uri("tile://localhost/2-7-1/"), Some(key));
+        assert_eq!(parse_tile_uri("tile://localhost/7-1"), None);
+        assert_eq!(store.take((1, 7, 1)), None);
+        assert_eq!(store.take(key), Some(vec![1, 2, 3, 4]));
+        assert_eq!(store.take(key), None);
+        assert!(store.take((2, 7, 8)).is_some());
+    }
+
+    #[test]
+    fn keeps_passes_of_stored_tiles() {
+        let store = TileStore::default();
+        for id in 0..MAX_STORED_TILES as TileId {
+            for step in progressive::STEPS {
+                store.insert((0, id, step), 0, vec![]);
+            }
+        }
+        assert!(store.take((0, 0, 1)).is_some());
+        assert!(store.take((0, 0, 2)).is_none());
+    }
+
+    #[test]
+    fn drops_cancelled_passes() {
+        let store = TileStore::default();
+        store.insert((1, 0, 1), 3, vec![0]);
+        store.insert((2, 1, 1), 2, vec![1]);
+        store.insert((2, 2, 1), 3, vec![2]);
+        store.insert((2, 3, 8), 3, vec![3]);
+        store.cancel(2, &[2], 3);
+        assert_eq!(store.take((1, 0, 1)), None);
+        assert_eq!(store.take((2, 1, 1)), None);
+        assert_eq!(store.take((2, 2, 1)), None);
+        assert_eq!(store.take((2, 3, 8)), Some(vec![3]));
+    }
+
+    #[test]
+    fn renders_batch_with_shared_setup() {
+        let tiles = batch(&[0.0, 0.0]).into_queued();
+        assert!(Arc::ptr_eq(&tiles[0].setup, &tiles[1].setup));
+        let (cache, running) = (FieldCache::default(), RunningTiles::default());
+        let pixels = render_tile(tiles[1].clone(), &cache, &running, |_, _| ()).unwrap();
+        assert_eq!(pixels.len(), 8 * 8 * 4);
+    }
+
+    #[test]
+    fn mirrors_cached_tiles() {
+        let tile = batch(&[0.0]).into_queued().remove(0);
+        let mirrored = QueuedTile {
+            fragment: Symmetry::RealAxis.mirror_fragment(&tile.fragment),
+            ..tile.clone()
+        };
+        let (cache, running) = (FieldCache::default(), RunningTiles::default());
+        let fresh = render_tile(mirrored.clone(), &cache, &running, |_, _| ()).unwrap();
+
+        let cache = FieldCache::default();
+        render_tile(tile, &cache, &running, |_, _| ());
+        let pixels = render_tile(mirrored, &cache, &running, |_, _| ()).unwrap();
+        assert_eq!(pixels, fresh);
+    }
+
+    #[test]
+    fn renders_in_priority_order() {
+        let (sender, receiver) = mpsc::channel();
+        let queue = TileQueue::new(1, move |tile| sender.send(tile.id).unwrap());
+        queue.schedule(batch(&[3.0, 1.0, 2.0]).into_queued());
+        let rendered: Vec<_> = receiver.iter().take(3).collect();
+        assert_eq!(rendered, [1, 2, 0]);
+    }
+
+    #[test]
+    fn reprioritizes_and_drops_tiles() {
+        let queue = TileQueue::default();
+        let mut tiles = batch(&[0.0, 1.0, 2.0, 3.0]).into_queued();
+        tiles[0].generation = 0;
+        for tile in &mut tiles[1..] {
+            tile.generation = 1;
+        }
+        queue.schedule(tiles);
+        let priorities = [TilePriority {
+            id: 3,
+            priority: -1.0,
+        }];
+        queue.prioritize(&priorities);
+        assert_eq!(queue.cancel(&[2], 1), 2);
+        assert_eq!(queue.pop().id, 3);
+        assert_eq!(queue.pop().id, 1);
+    }
+}
@@ -0,0 +1,174 @@
+//! Uncompressed YUV4MPEG2 (Y4M) streams, which can be piped
+//! straight into video encoders, e.g. `ffmpeg -i - out.mp4`.
+//! Colors are converted with BT.601 coefficients (limited range),
+//! which is what encoders assume for Y4M input.
+
+use crate::{data::Chroma, renderer::ImageBuffer};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+/// Path standing for the standard output
+pub const STDOUT: &str = "-";
+
+pub fn is_y4m_path(path: impl AsRef<Path>) -> bool {
+    let path = path.as_ref();
+    let extension = path.extension().and_then(|ext| ext.to_str());
+    path == Path::new(STDOUT) || extension.is_some_and(|ext| ext.eq_ignore_ascii_case("y4m"))
+}
+
+/// Opens the file, or the standard output for "-"
+pub fn open(path: impl AsRef<Path>) -> io::Result<Box<dyn Write>> {
+    let path = path.as_ref();
+    match path == Path::new(STDOUT) {
+        true => Ok(Box::new(BufWriter::new(io::stdout().lock()))),
+        false => Ok(Box::new(BufWriter::new(File::create(path)?))),
+    }
+}
+
+fn rgb_to_yuv(pixel: &[u8]) -> [f64; 3] {
+    let [r, g, b] = [pixel[0] as f64, pixel[1] as f64, pixel[2] as f64];
+    [
+        16.0 + 0.256788 * r + 0.504129 * g + 0.097906 * b,
+        128.0 - 0.148223 * r - 0.290993 * g + 0.439216 * b,
+        128.0 + 0.439216 * r - 0.367788 * g - 0.071427 * b,
+    ]
+}
+
+pub struct Y4mWriter<W: Write> {
+    writer: W,
+    width: u32,
+    height: u32,
+    chroma: Chroma,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    /// Writes the stream header, frames have to be width x height pixels
+    pub fn new(
+        mut writer: W,
+        width: u32,
+        height: u32,
+        frame_rate: u32,
+        chroma: Chroma,
+    ) -> io::Result<Self> {
+        let colorspace = match chroma {
+            Chroma::C420 => "420jpeg",
+            Chroma::C444 => "444",
+        };
+        writeln!(
+            writer,
+            "YUV4MPEG2 W{width} H{height} F{frame_rate}:1 Ip A1:1 C{colorspace}"
+        )?;
+        Ok(Self {
+            writer,
+            width,
+            height,
+            chroma,
+        })
+    }
+
+    /// Y, U and V planes of the image. Subsampled chroma
+    /// averages blocks of 2x2 pixels (sited in their centers)
+    fn planes(&self, image: &ImageBuffer) -> [Vec<u8>; 3] {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let yuv: Vec<[f64; 3]> = image.as_raw().chunks_exact(3).map(rgb_to_yuv).collect();
+        let luma = yuv.iter().map(|pixel| pixel[0].round() as u8).collect();
+        let (u, v) = match self.chroma {
+            Chroma::C444 => yuv
+                .iter()
+                .map(|pixel| (pixel[1].round() as u8, pixel[2].round() as u8))
+                .unzip(),
+            Chroma::C420 => {
+                let mut planes = (vec![], vec![]);
+                for y in (0..height).step_by(2) {
+                    for x in (0..width).step_by(2) {
+                        let block: Vec<_> = [(x, y), (x + 1, y), (x, y + 1), (x + 1, y + 1)]
+                            .into_iter()
+                            .filter(|&(x, y)| x < width && y < height)
+                            .map(|(x, y)| yuv[y * width + x])
+                            .collect();
+                        let average = |id: usize| {
+                            let sum: f64 = block.iter().map(|pixel| pixel[id]).sum();
+                            (sum / block.len() as f64).round() as u8
+                        };
+                        planes.0.push(average(1));
+                        planes.1.push(average(2));
+                    }
+                }
+                planes
+            }
+        };
+        [luma, u, v]
+    }
+
+    pub fn write_frame(&mut self, image: &ImageBuffer) -> io::Result<()> {
+        if image.dimensions() != (self.width, self.height) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "frame size differs from the stream size",
+            ));
+        }
+        self.writer.write_all(b"FRAME\n")?;
+        for plane in self.planes(image) {
+            self.writer.write_all(&plane)?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Saves a single image as a one frame stream
+pub fn save_y4m(image: &ImageBuffer, path: impl AsRef<Path>, chroma: Chroma) -> io::Result<()> {
+    let (width, height) = image.dimensions();
+    let mut stream = Y4mWriter::new(open(path)?, width, height, 1, chroma)?;
+    stream.write_frame(image)?;
+    stream.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Rgb;
+
+    #[test]
+    fn recognizes_y4m_paths() {
+        assert!(is_y4m_path("clip.Y4M"));
+        assert!(is_y4m_path(STDOUT));
+        assert!(!is_y4m_path("frames"));
+    }
+
+    #[test]
+    fn converts_to_limited_range() {
+        let [y, u, v] = rgb_to_yuv(&[255, 255, 255]);
+        assert!((y - 235.0).abs() < 0.01);
+        assert!((u - 128.0).abs() < 0.01 && (v - 128.0).abs() < 0.01);
+        let [y, _, _] = rgb_to_yuv(&[0, 0, 0]);
+        assert_eq!(y, 16.0);
+    }
+
+    #[test]
+    fn subsamples_odd_sized_frames() {
+        let image = ImageBuffer::from_pixel(3, 3, Rgb::from([255, 0, 0]));
+        let mut stream = Y4mWriter::new(vec![], 3, 3, 30, Chroma::C420).unwrap();
+        stream.write_frame(&image).unwrap();
+        let bytes = stream.finish().unwrap();
+
+        let header = b"YUV4MPEG2 W3 H3 F30:1 Ip A1:1 C420jpeg\nFRAME\n";
+        assert!(bytes.starts_with(header));
+        assert_eq!(bytes.len(), header.len() + 9 + 4 + 4);
+    }
+
+    #[test]
+    fn refuses_frames_of_other_sizes() {
+        let image = ImageBuffer::new(4, 4);
+        let mut stream = Y4mWriter::new(vec![], 3, 3, 30, Chroma::C444).unwrap();
+        assert!(stream.write_frame(&image).is_err());
+    }
+}
@@ -1,7 +1,8 @@
-import { Fractal, Size } from "../shared";
+import { Complex, Fractal, Size, TILE_SIZE_PX } from "../shared";
 import { Ticker } from "./ticker";
 import { RequestQueue } from "./scheduler";
 import { ScreenPosition } from "./screenPosition";
+import { Position } from "./position";
 import { TileManager } from "./tileManager";
 import { ScreenRenderer } from "./screenRenderer";
 import { Stage } from "./stage";
@@ -29,7 +30,7 @@ class FractalApp {
       this.stage.sortTiles();
       this.tiles.invalidateCache();
       this.queue.cancelStaleJobs();
-      this.queue.runNextJob();
+      this.queue.sendJobs();
       this.renderer.render(this.stage);
 
       if (this.ticker.canStop()) this.ticker.stop();
@@ -50,6 +51,18 @@ class FractalApp {
     this.screen.onConfigChanged(newVariant);
   }
 
+  showFragment(topLeft: Complex, bottomRight: Complex) {
+    const widthComplex = bottomRight.re - topLeft.re;
+    const pixelToComplex = widthComplex / this.renderer.width;
+    const level = Math.log2(pixelToComplex * TILE_SIZE_PX);
+    const center = new Position(
+      (topLeft.re + bottomRight.re) / 2,
+      (topLeft.im + bottomRight.im) / 2,
+      level
+    );
+    this.screen.setGoingTo(center);
+  }
+
   mountAt(root: HTMLDivElement) {
     const canvas = this.renderer.view as HTMLCanvasElement;
     canvas.classList.add("absolute");
@@ -1,21 +1,40 @@
 import { Texture } from "pixi.js";
-import { FractalFragment, calcTile } from "../api";
-import { TILE_SIZE_PX, distanceManhatan } from "../shared";
+import {
+  calcTiles,
+  cancelTiles,
+  fetchTile,
+  onTileRendered,
+  prioritizeTiles,
+  startTileSession,
+} from "../api";
+import type { FractalFragment, RenderedTile } from "../api/types";
+import { Complex, TILE_SIZE_PX, distanceManhatan } from "../shared";
 import type { Tile } from "./tile";
 import type { ScreenPosition } from "./screenPosition";
 import { store } from "../store";
 import { Ticker } from "./ticker";
 
-export type RequestError = "configOutdated" | "outOfScreen";
+export type RequestError = "configOutdated" | "outOfScreen" | "notStored";
 export type RequestResult = {
   texture: Texture;
   renderedForConfig: string;
 };
 
+const LEVEL_PRIORITY = 1000;
+// ^Tiles of higher levels go first, distance to the center breaks ties
+
+let nextJobId = 0;
+// tiles requested before the config changed belong to older generations
+let generation = 0;
+// known once the backend dropped the tiles of the previous page load
+let session: number | null = null;
+
 class RenderJob {
+  readonly id = nextJobId++;
+  readonly renderedForConfig = store.getHash();
   tile: Tile;
   promise: Promise<RequestResult>;
-  status: "waiting" | "rendering" | "done" | "canceled" = "waiting";
+  status: "waiting" | "done" | "canceled" = "waiting";
   private onResolve?: (rendered: RequestResult) => void;
   private onReject?: (error: RequestError) => void;
 
@@ -27,7 +46,7 @@ class RenderJob {
     });
   }
 
-  private request(): FractalFragment {
+  fragment(): FractalFragment {
     const bounds = this.tile.bounds();
     return {
       width_px: TILE_SIZE_PX,
@@ -43,21 +62,43 @@ class RenderJob {
     };
   }
 
-  async run() {
-    this.status = "rendering";
-    const hash = store.getHash();
-    const dataUrl = await calcTile(this.request());
-    if ((this.status as string) === "canceled") return;
+  priority(center: Complex) {
+    const tileSize = 2 ** this.tile.level;
+    const distance = distanceManhatan(this.tile.center(), center) / tileSize;
+    return distance - this.tile.level * LEVEL_PRIORITY;
+  }
+
+  /** Shows a coarse pass, until the tile is finished */
+  async refine(session: number, step: number) {
+    const pixels = await fetchTile(session, this.id, step).catch(() => null);
+    // ^Coarse passes are dropped, once the finished tile is stored
+    if (pixels === null || this.status !== "waiting") return;
+    const size = TILE_SIZE_PX;
+    this.tile.texture = Texture.fromBuffer(pixels, size, size);
+    if (this.tile.status === "loading") this.tile.status = "refining";
+  }
+
+  finish(session: number, ready: boolean) {
+    if (this.status === "canceled") return;
     // ^Requests can be canceled while being rendered
+    if (!ready) {
+      this.cancel("configOutdated");
+      return;
+    }
 
-    Texture.fromURL(dataUrl).then((texture) => {
-      if (this.onResolve === undefined)
-        throw "Can't resolve a promise that was not awaited";
-      this.onResolve({
-        texture,
-        renderedForConfig: hash,
-      });
-    });
+    fetchTile(session, this.id).then(
+      (pixels) => {
+        if (this.onResolve === undefined)
+          throw "Can't resolve a promise that was not awaited";
+        const size = TILE_SIZE_PX;
+        this.onResolve({
+          texture: Texture.fromBuffer(pixels, size, size),
+          renderedForConfig: this.renderedForConfig,
+        });
+      },
+      () => this.cancel("notStored"),
+      // ^Pushed out of the store before it was fetched
+    );
     this.status = "done";
   }
 
@@ -69,84 +110,94 @@ class RenderJob {
   }
 }
 
+/**
+ * Tiles are rendered by the backend, in the order of their priorities.
+ * Here they are only sent in batches, once per frame, together with
+ * new priorities whenever the screen moves.
+ */
 export class RequestQueue {
   private readonly ticker: Ticker;
   private readonly screen: ScreenPosition;
-  private readonly queue = new Map<number, RenderJob[]>();
-  private running: RenderJob | null = null;
+  private readonly jobs = new Map<number, RenderJob>();
+  private unsent: RenderJob[] = [];
+  private prioritizedFor: Complex | null = null;
   constructor(ticker: Ticker, screen: ScreenPosition) {
     this.ticker = ticker;
     this.screen = screen;
+    onTileRendered((tile) => this.onRendered(tile));
+    startTileSession().then((started) => {
+      session = started;
+      this.ticker.start();
+    });
   }
 
-  private popJobClosestToCenter(queue: RenderJob[]): RenderJob {
-    let closestId = 0;
-    let closestDistance = Infinity;
-    let center = this.screen.current.center;
-    for (let id = 0; id < queue.length; id++) {
-      const position = queue[id].tile.center();
-      const distance = distanceManhatan(position, center);
-      if (distance < closestDistance) {
-        closestDistance = distance;
-        closestId = 0;
-      }
-    }
-
-    const job = queue[closestId];
-    queue.splice(closestId, 1);
-    return job;
-  }
-
-  private popMostImportantJob(): RenderJob | null {
-    let highestLevel = -Infinity;
-    for (const level of this.queue.keys()) {
-      highestLevel = Math.max(highestLevel, level);
+  private onRendered({ id, session: tileSession, step, ready }: RenderedTile) {
+    if (tileSession !== session) return;
+    const job = this.jobs.get(id);
+    if (job === undefined) return;
+    if (step > 1) {
+      job.refine(tileSession, step).then(() => this.ticker.start());
+      return;
     }
-
-    const queue = this.queue.get(highestLevel);
-    if (queue === undefined) return null;
-    const job = this.popJobClosestToCenter(queue);
-    if (queue.length === 0) this.queue.delete(highestLevel);
-    return job ?? null;
+    this.jobs.delete(id);
+    job.finish(tileSession, ready);
+    this.ticker.start();
   }
 
-  async runNextJob() {
-    if (this.running?.status === "rendering") return;
-    while (true) {
-      this.running = this.popMostImportantJob();
-      if (this.running === null) return;
-      await this.running.run();
-      this.ticker.start();
+  sendJobs() {
+    if (session === null) return;
+    const center = this.screen.current.center;
+    const moved =
+      this.prioritizedFor?.re !== center.re ||
+      this.prioritizedFor?.im !== center.im;
+    const sent = this.jobs.size - this.unsent.length;
+    if (moved && sent > 0) {
+      const unsent = new Set(this.unsent);
+      const priorities = [...this.jobs.values()]
+        .filter((job) => !unsent.has(job))
+        .map((job) => ({ id: job.id, priority: job.priority(center) }));
+      prioritizeTiles(priorities);
     }
+    this.prioritizedFor = { re: center.re, im: center.im };
+
+    if (this.unsent.length === 0) return;
+    const tiles = this.unsent.map((job) => ({
+      id: job.id,
+      fragment: job.fragment(),
+      priority: job.priority(center),
+    }));
+    calcTiles(tiles, session, generation);
+    this.unsent = [];
   }
 
   schedule(tile: Tile): Promise<RequestResult> {
     const job = new RenderJob(tile);
-    const queueForLevel = this.queue.get(tile.level) ?? [];
-    queueForLevel.push(job);
-    this.queue.set(tile.level, queueForLevel);
+    this.jobs.set(job.id, job);
+    this.unsent.push(job);
     return job.promise;
   }
 
   cancelStaleJobs() {
     const thisFrame = this.ticker.drawingAt;
-    for (const [level, levelQueue] of this.queue.entries()) {
-      for (let id = 0; id < levelQueue.length; ) {
-        const job = levelQueue[id];
-        const isNeeded = job.tile.lastUsedAt === thisFrame;
-        if (isNeeded) id++;
-        else {
-          job.cancel("outOfScreen");
-          levelQueue.splice(id, 1);
-        }
-      }
-
-      if (levelQueue.length === 0) this.queue.delete(level);
+    const staleIds: number[] = [];
+    for (const job of this.jobs.values()) {
+      const isNeeded = job.tile.lastUsedAt === thisFrame;
+      if (isNeeded) continue;
+      job.cancel("outOfScreen");
+      this.jobs.delete(job.id);
+      staleIds.push(job.id);
     }
+
+    if (staleIds.length === 0) return;
+    this.unsent = this.unsent.filter((job) => job.status !== "canceled");
+    cancelTiles(staleIds, generation);
   }
 
-  cancelRunningJob() {
-    this.running?.cancel("configOutdated");
-    this.running = null;
+  cancelOutdatedJobs() {
+    for (const job of this.jobs.values()) job.cancel("configOutdated");
+    this.jobs.clear();
+    this.unsent = [];
+    generation++;
+    cancelTiles([], generation);
   }
 }
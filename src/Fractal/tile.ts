@@ -5,7 +5,7 @@ import type { ScreenRenderer, Bounds } from "./screenRenderer";
 const { floor } = Math;
 export class Tile extends Sprite {
   private static cache = new Map<string, Tile>();
-  status: "ready" | "empty" | "loading" | "updating" = "empty";
+  status: "ready" | "empty" | "loading" | "refining" | "updating" = "empty";
   lastUsedAt = -1;
   renderedForConfig: string = "";
   readonly hash: string;
@@ -91,7 +91,11 @@ export class Tile extends Sprite {
   }
 
   canBeDrawn() {
-    return this.status === "ready" || this.status === "updating";
+    return (
+      this.status === "ready" ||
+      this.status === "refining" ||
+      this.status === "updating"
+    );
   }
 
   updatePosition(renderer: ScreenRenderer) {
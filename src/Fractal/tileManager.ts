@@ -64,6 +64,7 @@ export class TileManager {
     tile.lastUsedAt = this.ticker.drawingAt;
     switch (tile.status) {
       case "loading":
+      case "refining":
       case "updating":
         return;
       case "empty":
@@ -82,7 +83,11 @@ export class TileManager {
     } catch (err) {
       if (err === "configOutdated") tile.destroy();
       else if (err === "outOfScreen") tile.status = "empty";
-      else throw err;
+      else if (err === "notStored") {
+        tile.status = "empty";
+        this.ticker.start();
+        // ^Next frame schedules it again, if it's still on screen
+      } else throw err;
     }
   }
 
@@ -122,7 +127,7 @@ export class TileManager {
     if (this.lastConfig === this.currentConfig) return;
     const timestamp = this.ticker.drawingAt;
     Tile.deleteStaleCache(timestamp, this.currentConfig);
-    this.queue.cancelRunningJob();
+    this.queue.cancelOutdatedJobs();
     this.lastConfig = this.currentConfig;
   }
 
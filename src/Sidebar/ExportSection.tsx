@@ -1,5 +1,12 @@
 import { Show } from "solid-js";
-import { onExportRequest } from "../api";
+import {
+  onCancelExport,
+  onExportRequest,
+  onLoadScene,
+  onOpenExportedImage,
+  onResumeExport,
+  onSaveScene,
+} from "../api";
 import { HasChild } from "../shared";
 import { AppStore, store } from "../store";
 import { SectionHeader } from "./SectionHeader";
@@ -8,7 +15,10 @@ type Status = AppStore["export"]["status"];
 const userFeedback: Record<Status, string> = {
   done: "Export finished",
   errorBadFileType: "Error: bad file type",
+  errorNoDiskSpace: "Error: not enough disk space",
+  cancelled: "Export cancelled",
   errorUnknown: "Unknown error :(",
+  queued: "Queued",
   exporting: "Exporting",
   idle: "Export",
   pickingFilePath: "Waiting for filepath",
@@ -31,9 +41,8 @@ const ButtonExportSource = (props: ExportSource) => {
 
 export const ExportSection = () => {
   const status = () => store.exportConfig.get.status;
-  const waiting = () => {
-    return status() === "exporting" || status() === "pickingFilePath";
-  };
+  const waiting = () => status() === "pickingFilePath";
+  const pending = () => status() === "queued" || status() === "exporting";
   return (
     <form
       class="grow flex flex-col gap-3 justify-end"
@@ -55,10 +64,44 @@ export const ExportSection = () => {
         disabled={waiting()}
       >
         {userFeedback[status()]}
-        <Show when={waiting()}>
+        <Show when={status() === "exporting"}>
+          {Math.round(store.exportConfig.get.progress * 100)}%
+        </Show>
+        <Show when={waiting() || pending()}>
           <span class="loading loading-dots loading-sm"></span>
         </Show>
       </button>
+      <Show when={pending()}>
+        <progress
+          class="progress progress-primary w-full"
+          value={store.exportConfig.get.progress}
+          max="1"
+        />
+        <button class="btn btn-outline btn-sm w-full" onClick={onCancelExport}>
+          cancel export
+        </button>
+      </Show>
+      <div class="flex gap-1">
+        <button class="btn btn-ghost btn-sm flex-1" onClick={onSaveScene}>
+          save scene
+        </button>
+        <button class="btn btn-ghost btn-sm flex-1" onClick={onLoadScene}>
+          load scene
+        </button>
+        <button
+          class="btn btn-ghost btn-sm flex-1"
+          onClick={onOpenExportedImage}
+        >
+          open image
+        </button>
+        <button
+          class="btn btn-ghost btn-sm flex-1"
+          onClick={onResumeExport}
+          disabled={waiting()}
+        >
+          resume export
+        </button>
+      </div>
     </form>
   );
 };
@@ -1,9 +1,26 @@
 import { Show } from "solid-js";
+import type { RenderStrategy } from "../api/types";
+import { HasChild } from "../shared";
 import { store } from "../store";
 import { FractalEquation } from "./FractalEquation";
 import { FractalSectionHeader } from "./FractalSectionHeader";
 import { InputNumber } from "./InputNumber";
 
+type Strategy = HasChild & { strategy: RenderStrategy };
+const ButtonStrategy = (props: Strategy) => {
+  return (
+    <button
+      class="btn btn-primary btn-sm flex-1"
+      onClick={() => store.fractal.setStrategy(props.strategy)}
+      classList={{
+        "btn-outline": props.strategy !== store.fractal.get.strategy,
+      }}
+    >
+      {props.children}
+    </button>
+  );
+};
+
 export const FractalSection = () => {
   const BOUNDS = {
     max: 5,
@@ -49,6 +66,12 @@ export const FractalSection = () => {
           description: "yeah, I barely get it myself ;-;",
         }}
       />
+      <div class="flex gap-1 items-center">
+        <span class="text-sm opacity-80">Skip areas by</span>
+        <ButtonStrategy strategy="Off">nothing</ButtonStrategy>
+        <ButtonStrategy strategy="Subdivision">subdivision</ButtonStrategy>
+        <ButtonStrategy strategy="BoundaryTracing">tracing</ButtonStrategy>
+      </div>
     </div>
   );
 };
@@ -1,13 +1,97 @@
-import { invoke } from "@tauri-apps/api";
-import { CalcTileRequest, FractalFragment } from "./types";
+import { event, invoke, tauri } from "@tauri-apps/api";
+import {
+  BatchTile,
+  FractalFragment,
+  RenderedTile,
+  TilePriority,
+  TileRequest,
+} from "./types";
 import { getColorConfig, getFractalConfig } from "./utils";
+import { store } from "../store";
 
-export const calcTile = async (req: FractalFragment): Promise<string> => {
-  const request: CalcTileRequest = {
-    fragment: req,
+export const tileRequest = (
+  fragment: FractalFragment,
+  id: number,
+  session: number,
+  generation: number
+): TileRequest => ({
+  fragment,
+  fractal: getFractalConfig(),
+  color: getColorConfig(),
+  id,
+  session,
+  generation,
+  progressive: false,
+  strategy: store.fractal.get.strategy,
+});
+
+/** Resolves to false, when the tile was cancelled */
+export const calcTile = async (
+  req: FractalFragment,
+  id: number,
+  session: number,
+  generation: number
+): Promise<boolean> => {
+  const request = tileRequest(req, id, session, generation);
+  return await invoke<boolean>("calc_tile", { request });
+};
+
+/**
+ * Tiles requested before the page (re)loaded are dropped, ids and
+ * generations of the new session start from zero again
+ */
+export const startTileSession = async (): Promise<number> => {
+  return await invoke<number>("start_tile_session");
+};
+
+/**
+ * Raw RGBA pixels of a rendered pass of a tile, top row first.
+ * Every pass can be fetched once
+ */
+export const fetchTile = async (
+  session: number,
+  id: number,
+  step: number = 1
+): Promise<Uint8Array> => {
+  const url = tauri.convertFileSrc(`${session}-${id}-${step}`, "tile");
+  const response = await fetch(url);
+  if (!response.ok) throw `Tile ${id} is not available`;
+  return new Uint8Array(await response.arrayBuffer());
+};
+
+/**
+ * Renders the tiles with the current config, lower priorities first.
+ * Coarse passes and finished tiles are announced one by one,
+ * see onTileRendered
+ */
+export const calcTiles = async (
+  tiles: BatchTile[],
+  session: number,
+  generation: number
+) => {
+  const batch = {
     fractal: getFractalConfig(),
     color: getColorConfig(),
+    session,
+    generation,
+    progressive: true,
+    strategy: store.fractal.get.strategy,
+    tiles,
   };
+  await invoke("calc_tiles", { batch });
+};
+
+export const prioritizeTiles = async (priorities: TilePriority[]) => {
+  await invoke("prioritize_tiles", { priorities });
+};
+
+export const onTileRendered = (callback: (tile: RenderedTile) => void) => {
+  return event.listen<RenderedTile>("tile_rendered", ({ payload }) =>
+    callback(payload)
+  );
+};
 
-  return await invoke<string>("calc_tile", { request });
+/** Cancels the listed tiles and all tiles from older generations */
+export const cancelTiles = async (ids: number[], generation: number) => {
+  await invoke<number>("cancel_tiles", { ids, generation });
 };
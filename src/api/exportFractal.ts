@@ -1,17 +1,56 @@
-import { dialog, invoke } from "@tauri-apps/api";
+import { dialog, event, invoke } from "@tauri-apps/api";
 import { fractalApp } from "../Fractal/fractalApp";
 import { store } from "../store";
-import { ExportFractalRequest } from "./types";
+import {
+  ExportFractalRequest,
+  ExportResult,
+  JobInfo,
+  JobStatus,
+} from "./types";
 import { getColorConfig, getFractalConfig } from "./utils";
 
 const state = store.exportConfig;
 
+const resultToStatus: Record<ExportResult, typeof state.get.status> = {
+  Done: "done",
+  ErrorBadFileType: "errorBadFileType",
+  ErrorNoDiskSpace: "errorNoDiskSpace",
+  ErrorUnknown: "errorUnknown",
+  Cancelled: "cancelled",
+};
+
+const showStatus = (status: JobStatus) => {
+  if (status.state === "queued") {
+    state.set("status", "queued");
+  } else if (status.state === "running") {
+    state.set("status", "exporting");
+    state.set("progress", status.progress);
+  } else {
+    state.set("status", resultToStatus[status.result]);
+  }
+};
+
+// only the last enqueued job is shown
+event.listen<JobInfo>("export_job", ({ payload: job }) => {
+  if (job.id === state.get.job) showStatus(job.status);
+});
+
+const enqueue = async (command: string, args: Record<string, unknown>) => {
+  state.set("progress", 0);
+  state.set("status", "queued");
+  const id = await invoke<number>(command, args);
+  state.set("job", id);
+  // events sent before the id came back were missed
+  const status = await invoke<JobStatus | null>("export_status", { id });
+  if (status !== null) showStatus(status);
+};
+
 const getDefaultSaveDir = async () => {
   let path = await invoke<null | string>("get_default_save_dir");
   return path ?? undefined;
 };
 
-const getViewportSelection = () => {
+export const getViewportSelection = () => {
   if (state.get.source === "screen")
     return {
       left: 0,
@@ -50,7 +89,15 @@ export const onExportRequest = async () => {
     filters: [
       {
         name: store.fractal.get.variant,
-        extensions: ["png", "jpeg"],
+        extensions: ["png", "jpeg", "tiff"],
+      },
+      {
+        name: "HDR image",
+        extensions: ["exr"],
+      },
+      {
+        name: "Iteration data",
+        extensions: ["npy", "raw"],
       },
     ],
   });
@@ -70,16 +117,27 @@ export const onExportRequest = async () => {
       bottom_right: bottomRight,
     },
     filepath,
+    strategy: store.fractal.get.strategy,
   };
 
-  state.set("status", "exporting");
-  type Result = "ErrorUnknown" | "ErrorBadFileType" | "Done";
-  const result = await invoke<Result>("export_image", { request });
-  const resultToStatus: Record<Result, typeof state.get.status> = {
-    Done: "done",
-    ErrorBadFileType: "errorBadFileType",
-    ErrorUnknown: "errorUnknown",
-  };
-  const status = resultToStatus[result];
-  state.set("status", status);
+  await enqueue("enqueue_export", { request });
+};
+
+export const onCancelExport = async () => {
+  if (state.get.job === null) return;
+  await invoke<boolean>("cancel_export", { id: state.get.job });
+};
+
+export const onResumeExport = async () => {
+  state.set("status", "pickingFilePath");
+  const manifest = await dialog.open({
+    title: "Pick the manifest of an unfinished export",
+    filters: [{ name: "Export manifest", extensions: ["json"] }],
+  });
+  if (typeof manifest !== "string") {
+    state.set("status", "idle");
+    return;
+  }
+
+  await enqueue("resume_export", { manifest });
 };
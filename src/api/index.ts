@@ -1,3 +1,5 @@
 export * from "./calcTile";
 export * from "./exportFractal";
+export * from "./openExportedImage";
+export * from "./scene";
 export type { FractalFragment } from "./types";
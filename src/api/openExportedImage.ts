@@ -0,0 +1,51 @@
+import { dialog, invoke } from "@tauri-apps/api";
+import { batch } from "solid-js";
+import { fractalApp } from "../Fractal/fractalApp";
+import { ColoringMethod, Fractal } from "../shared";
+import { store } from "../store";
+import { RenderParams } from "./types";
+
+export const restoreParams = ({ fractal, color, fragment }: RenderParams) => {
+  const { variant } = fractal;
+  const { method } = color;
+  batch(() => {
+    store.fractal.restore({
+      variant: variant.type as Fractal,
+      maxIterations: fractal.max_iterations,
+      constant: "constant" in variant ? { ...variant.constant } : null,
+    });
+    store.coloring.restore({
+      method: method.type as ColoringMethod,
+      exponent:
+        method.type === "Exponential"
+          ? method.power
+          : store.coloring.get.exponent,
+      antialiasing: color.anti_alias,
+      brightness: color.brightness,
+      color: color.color,
+    });
+  });
+  fractalApp.showFragment(fragment.top_left, fragment.bottom_right);
+};
+
+export const onOpenExportedImage = async () => {
+  const filepath = await dialog.open({
+    title: "Open an exported fractal",
+    filters: [
+      {
+        name: "Exported images",
+        extensions: ["png", "jpeg", "jpg"],
+      },
+    ],
+  });
+  if (typeof filepath !== "string") return;
+
+  const params = await invoke<RenderParams | null>("read_render_params", {
+    filepath,
+  });
+  if (params === null) {
+    console.warn(`No fractal parameters found in ${filepath}`);
+    return;
+  }
+  restoreParams(params);
+};
@@ -0,0 +1,85 @@
+import { dialog, invoke } from "@tauri-apps/api";
+import { fractalApp } from "../Fractal/fractalApp";
+import { store } from "../store";
+import { getViewportSelection } from "./exportFractal";
+import { restoreParams } from "./openExportedImage";
+import { FractalFragment, Scene } from "./types";
+import { getColorConfig, getFractalConfig } from "./utils";
+
+const SCENE_VERSION = 1;
+const sceneFilters = [
+  {
+    name: "Fractal scene",
+    extensions: ["json"],
+  },
+];
+
+const getViewport = (): FractalFragment => {
+  const { width, height } = store.viewer.get;
+  const { renderer } = fractalApp;
+  return {
+    width_px: width,
+    height_px: height,
+    top_left: renderer.viewportToComplex({ x: 0, y: 0 }),
+    bottom_right: renderer.viewportToComplex({ x: width, y: height }),
+  };
+};
+
+const restoreExportSettings = (settings: Scene["export"]) => {
+  const state = store.exportConfig;
+  state.set("width", settings.width_px);
+  const { selection } = settings;
+  if (selection === null) {
+    state.set("source", "screen");
+    return;
+  }
+  state.set("source", "selection");
+  state.setSelection("start", { x: selection.left, y: selection.top });
+  state.setSelection("end", { x: selection.right, y: selection.bottom });
+};
+
+export const onSaveScene = async () => {
+  const filepath = await dialog.save({
+    title: "Save the scene",
+    filters: sceneFilters,
+  });
+  if (filepath == null) return;
+
+  const exportFromSelection = store.exportConfig.get.source === "selection";
+  const scene: Scene = {
+    version: SCENE_VERSION,
+    fractal: getFractalConfig(),
+    color: getColorConfig(),
+    viewport: getViewport(),
+    export: {
+      width_px: store.exportConfig.get.width,
+      selection: exportFromSelection ? getViewportSelection() : null,
+    },
+  };
+
+  try {
+    await invoke("save_scene", { scene, filepath });
+  } catch (error) {
+    console.warn(`Could not save ${filepath}: ${error}`);
+  }
+};
+
+export const onLoadScene = async () => {
+  const filepath = await dialog.open({
+    title: "Open a scene",
+    filters: sceneFilters,
+  });
+  if (typeof filepath !== "string") return;
+
+  try {
+    const scene = await invoke<Scene>("load_scene", { filepath });
+    restoreParams({
+      fractal: scene.fractal,
+      color: scene.color,
+      fragment: scene.viewport,
+    });
+    restoreExportSettings(scene.export);
+  } catch (error) {
+    console.warn(`Could not load ${filepath}: ${error}`);
+  }
+};
@@ -38,6 +38,8 @@ export type ColorConfig = {
 export type FractalConfig = {
   variant: FractalVariant;
   max_iterations: number;
+  /** How close an orbit has to come back to itself, to count as a cycle */
+  cycle_tolerance?: number;
 };
 
 export type CalcTileRequest = {
@@ -46,6 +48,104 @@ export type CalcTileRequest = {
   color: ColorConfig;
 };
 
+/** How uniform areas are found, "Off" evaluates every pixel */
+export type RenderStrategy = "Off" | "Subdivision" | "BoundaryTracing";
+
+export type TileRequest = CalcTileRequest & {
+  id: number;
+  session: number;
+  generation: number;
+  progressive: boolean;
+  strategy?: RenderStrategy;
+};
+
+export type BatchTile = {
+  id: number;
+  fragment: FractalFragment;
+  priority: number;
+};
+
+export type TileBatch = {
+  fractal: FractalConfig;
+  color: ColorConfig;
+  session: number;
+  generation: number;
+  progressive: boolean;
+  strategy?: RenderStrategy;
+  tiles: BatchTile[];
+};
+
+export type TilePriority = {
+  id: number;
+  priority: number;
+};
+
+export type RenderedTile = {
+  id: number;
+  session: number;
+  generation: number;
+  /** Spacing of the samples, 1 for the finished tile */
+  step: number;
+  ready: boolean;
+};
+
+export type RenderParams = CalcTileRequest;
+
+export type Selection = {
+  left: number;
+  top: number;
+  right: number;
+  bottom: number;
+};
+
+export type Scene = {
+  version: number;
+  fractal: FractalConfig;
+  color: ColorConfig;
+  viewport: FractalFragment;
+  export: {
+    width_px: number;
+    selection: Selection | null;
+  };
+};
+
+export type ConstantPath =
+  | { type: "Polyline"; points: Complex[] }
+  | { type: "Circle"; center: Complex; radius: number }
+  | { type: "Cardioid" };
+
+export type LoopMotion =
+  | { type: "JuliaMorph"; path: ConstantPath }
+  | { type: "PaletteCycle" }
+  | { type: "Zoom"; factor: number };
+
+export type LoopAnimation = {
+  motion: LoopMotion;
+  frames: number;
+  delay_ms: number;
+  dither?: boolean;
+};
+
 export type ExportFractalRequest = CalcTileRequest & {
   filepath: string;
+  animation?: LoopAnimation;
+  strategy?: RenderStrategy;
+};
+
+export type ExportResult =
+  | "Done"
+  | "ErrorBadFileType"
+  | "ErrorNoDiskSpace"
+  | "ErrorUnknown"
+  | "Cancelled";
+
+export type JobStatus =
+  | { state: "queued" }
+  | { state: "running"; progress: number }
+  | { state: "finished"; result: ExportResult };
+
+export type JobInfo = {
+  id: number;
+  filepath: string;
+  status: JobStatus;
 };
@@ -23,9 +23,14 @@ const setColor = (which: "hex_start" | "hex_end", value: string) => {
   __setStore("coloring", "color", which, value);
 };
 
+const restore = (state: AppStore["coloring"]) => {
+  __setStore("coloring", { ...state, color: { ...state.color } });
+};
+
 export const coloring = {
   getColorHash,
   get: __store.coloring,
   setColor,
   set: setColoring,
+  restore,
 };
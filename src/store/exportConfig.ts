@@ -15,7 +15,13 @@ const getHeight = () => {
   return Math.floor(__store.export.width / getExportAspectRatio());
 };
 
-type DirectSetters = "status" | "progress" | "filepath" | "width" | "source";
+type DirectSetters =
+  | "status"
+  | "progress"
+  | "job"
+  | "filepath"
+  | "width"
+  | "source";
 const setExport = <Key extends DirectSetters>(
   key: Key,
   value: AppStore["export"][Key]
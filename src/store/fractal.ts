@@ -1,6 +1,7 @@
 import { batch } from "solid-js";
 import { Complex, FRACTAL_CONFIG, Fractal, complexToString } from "../shared";
-import { __setStore, __store, initConstant } from "./store";
+import { AppStore, __setStore, __store, initConstant } from "./store";
+import type { RenderStrategy } from "../api/types";
 
 const getConfig = () => FRACTAL_CONFIG[__store.fractal.variant];
 const getConstantOrThrow = (where: string) => {
@@ -31,10 +32,18 @@ const changeFractalVariant = (variant: Fractal) => {
   });
 };
 
+const restore = (state: Omit<AppStore["fractal"], "strategy">) => {
+  __setStore("fractal", { ...state });
+};
+
 const setMaxIterations = (iters: number) => {
   __setStore("fractal", "maxIterations", iters);
 };
 
+const setStrategy = (strategy: RenderStrategy) => {
+  __setStore("fractal", "strategy", strategy);
+};
+
 export const fractal = {
   get: __store.fractal,
   getFractalHash,
@@ -42,5 +51,7 @@ export const fractal = {
   getConstantOrThrow,
   changeFractalVariant,
   setMaxIterations,
+  setStrategy,
   setConstant,
+  restore,
 };
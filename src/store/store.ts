@@ -9,6 +9,7 @@ import {
   ColoringMethod,
   INIT_COLORING_METHOD,
 } from "../shared";
+import type { RenderStrategy } from "../api/types";
 
 export type AppStore = {
   viewer: Size;
@@ -26,16 +27,22 @@ export type AppStore = {
     maxIterations: number;
     constant: Complex | null;
     variant: Fractal;
+    // ^doesn't change the picture, only how fast it's rendered
+    strategy: RenderStrategy;
   };
   export: {
     status:
       | "idle"
       | "done"
+      | "queued"
       | "exporting"
       | "pickingFilePath"
       | "errorUnknown"
-      | "errorBadFileType";
+      | "errorBadFileType"
+      | "errorNoDiskSpace"
+      | "cancelled";
     progress: number;
+    job: number | null;
     filepath: string;
     width: number;
     source: "selection" | "screen";
@@ -73,10 +80,12 @@ const initStore: AppStore = {
     maxIterations: 128,
     variant: INIT_FRACTAL,
     constant: initConstant(INIT_FRACTAL),
+    strategy: "Off",
   },
   export: {
     status: "idle",
     progress: 0,
+    job: null,
     filepath: "",
     width: 3000,
     source: "screen",